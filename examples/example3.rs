@@ -4,7 +4,7 @@ extern crate dali;
 use std::f32::consts::PI;
 use std::fs::File;
 use std::path::Path;
-use std::fs::create_dir;
+use std::fs::create_dir_all;
 
 use image::{DynamicImage, ImageOutputFormat};
 use rand::{Rng, thread_rng};
@@ -70,7 +70,7 @@ pub fn main() {
     // dali renders fully opaque images, but handles transparency internally with premultiplied alpha
     // this means we can render to an opaque PNG, or JPEG (which doesn't support transparency)
     // here we use the DynamicImage::write_to method so we can control the JPEG compression level.
-    create_dir("out");
+    create_dir_all("out").expect("Could not create output directory.");
     println!("Writing to out/example3.jpg");
     let mut file = File::create("out/example3.jpg").expect("Could not create output file.");
     DynamicImage::ImageRgba8(image)
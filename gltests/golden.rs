@@ -0,0 +1,500 @@
+//! Renders canonical scenes, and compares them against the golden images in gltests/golden.
+//!
+//! Goldens are rendered by Mesa's llvmpipe.  After an intentional change to the output,
+//! regenerate them with `DALI_UPDATE_GOLDEN=1 cargo test --test gltests`, and check the new PNGs in.
+//! The software renderer is checked against the same goldens.
+//! When a comparison fails, the actual output and a diff image are written to target/gltests.
+
+use std::fs::create_dir_all;
+use std::path::PathBuf;
+
+use dali::{
+    Background, BlendMode, CanvasGate, ClipHandle, ColormapHandle, DaliBackend, DaliPipeline,
+    MaskHandle, Stipple, TextureHandle,
+};
+use image::{Rgba, RgbaImage};
+
+const SIZE: [u32; 2] = [128, 96];
+
+/// Pixels with a weighted channel difference below this are considered equal (out of 255)
+const PIXEL_TOLERANCE: f32 = 3.0;
+/// The fraction of pixels which may exceed PIXEL_TOLERANCE
+const MAX_DIFFERENT_PIXELS: f32 = 0.001;
+/// No pixel may exceed this weighted difference, even if there are few of them
+const MAX_PIXEL_DIFF: f32 = 48.0;
+
+pub struct Assets<B: DaliBackend> {
+    gradient: ColormapHandle<B>,
+    red: ColormapHandle<B>,
+    mask: MaskHandle<B>,
+    texture: TextureHandle<B>,
+    subject: ClipHandle<B>,
+    background: ClipHandle<B>,
+    ground: RgbaImage,
+}
+
+pub type Scene<B> = for<'a> fn(&mut CanvasGate<'a, B>, &'a Assets<B>);
+
+pub fn assets<B: DaliBackend>(pipeline: &mut DaliPipeline<B>) -> Assets<B> {
+    let mask = image::GrayImage::from_fn(64, 64, |x, y| {
+        let dx = x as f32 - 31.5;
+        let dy = y as f32 - 31.5;
+        let len = (dx * dx + dy * dy).sqrt() / 32.0;
+        image::Luma([(255.0 * (1.0 - len).max(0.0)) as u8])
+    });
+
+    let texture = image::GrayImage::from_fn(64, 64, |x, y| {
+        image::Luma([((x * 7 + y * 3) * 4 % 256) as u8])
+    });
+
+    // an ellipse in the center of the canvas, with a soft edge
+    let subject = image::GrayImage::from_fn(32, 24, |x, y| {
+        let dx = (x as f32 - 15.5) / 10.0;
+        let dy = (y as f32 - 11.5) / 9.0;
+        let len = (dx * dx + dy * dy).sqrt();
+        image::Luma([(255.0 * (4.0 * (1.0 - len)).clamp(0.0, 1.0)) as u8])
+    });
+    let mut background = subject.clone();
+    image::imageops::invert(&mut background);
+
+    Assets {
+        gradient: pipeline.colormap([32, 32], |x, y| [x, y, 1.0 - x, 1.0]),
+        red: pipeline.colormap([4, 4], |_x, _y| [0.9, 0.1, 0.1, 1.0]),
+        mask: pipeline.mask_from_image(mask, 6),
+        texture: pipeline.texture_from_image(texture, 6),
+        subject: pipeline.clip_from_image(subject),
+        background: pipeline.clip_from_image(background),
+        ground: RgbaImage::from_fn(16, 12, |x, y| {
+            Rgba([
+                (x * 16) as u8,
+                96,
+                (y * 20) as u8,
+                if x < 4 { 128 } else { 255 },
+            ])
+        }),
+    }
+}
+
+pub fn scenes<B: DaliBackend>() -> Vec<(&'static str, Scene<B>)> {
+    vec![
+        ("single_stipple", single_stipple::<B>),
+        ("rotated_stipple", rotated_stipple::<B>),
+        ("colormap_scale", colormap_scale::<B>),
+        ("textured_stipple", textured_stipple::<B>),
+        ("multi_layer", multi_layer::<B>),
+        ("tint_opacity", tint_opacity::<B>),
+        ("colormap_pickup", colormap_pickup::<B>),
+        ("sheared_stipple", sheared_stipple::<B>),
+        ("blend_modes", blend_modes::<B>),
+        ("layer_opacity", layer_opacity::<B>),
+        ("clipped_layers", clipped_layers::<B>),
+        ("toned_ground", toned_ground::<B>),
+        ("image_ground", image_ground::<B>),
+    ]
+}
+
+fn single_stipple<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            stipples.draw(Stipple::default().with_scale([0.5, 0.5]));
+        });
+    });
+}
+
+fn rotated_stipple<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([0.2, -0.1])
+                    .with_scale([0.7, 0.25])
+                    .with_rotation(0.6)
+                    .with_gamma(0.5),
+            );
+        });
+    });
+}
+
+fn colormap_scale<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            // nearly flat color, sampled from a blurred mip level
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([-0.5, 0.0])
+                    .with_scale([0.45, 0.6])
+                    .with_colormap_scale([0.05, 0.05]),
+            );
+            // the colormap stretched well past the stipple, and mirrored at the edges
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([0.5, 0.0])
+                    .with_scale([0.45, 0.6])
+                    .with_colormap_scale([4.0, 4.0]),
+            );
+        });
+    });
+}
+
+fn textured_stipple<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple_with_texture(&assets.mask, &assets.texture, |stipples| {
+            stipples.draw(
+                Stipple::default()
+                    .with_scale([0.8, 0.8])
+                    .with_texture_rotation(0.3),
+            );
+        });
+    });
+}
+
+fn multi_layer<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            for i in 0..5 {
+                let x = -0.6 + 0.3 * i as f32;
+                stipples.draw(
+                    Stipple::default()
+                        .with_translation([x, 0.2])
+                        .with_scale([0.3, 0.3]),
+                );
+            }
+        });
+    });
+
+    canvas.layer(&assets.red, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([0.0, -0.2])
+                    .with_scale([0.9, 0.3])
+                    .with_gamma(2.0),
+            );
+        });
+    });
+}
+
+fn tint_opacity<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            // a fade from transparent to opaque
+            for i in 0..5 {
+                let x = -0.6 + 0.3 * i as f32;
+                stipples.draw(
+                    Stipple::default()
+                        .with_translation([x, 0.4])
+                        .with_scale([0.25, 0.25])
+                        .with_opacity(0.2 * (i + 1) as f32),
+                );
+            }
+        });
+
+        layer.stipple_with_texture(&assets.mask, &assets.texture, |stipples| {
+            // a half-strength yellow glaze, over a full-strength blue tint
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([-0.2, -0.3])
+                    .with_scale([0.4, 0.4])
+                    .with_tint([0.1, 0.2, 0.9, 1.0]),
+            );
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([0.2, -0.3])
+                    .with_scale([0.4, 0.4])
+                    .with_tint([0.9, 0.8, 0.1, 0.5])
+                    .with_opacity(0.6),
+            );
+        });
+    });
+}
+
+fn colormap_pickup<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            // a stroke from left to right, which carries color from the left edge
+            for i in 0..6 {
+                let x = -0.75 + 0.3 * i as f32;
+                stipples.draw(
+                    Stipple::default()
+                        .with_translation([x, 0.35])
+                        .with_scale([0.2, 0.2])
+                        .with_colormap_translation([-0.75 - x, 0.0]),
+                );
+            }
+
+            // the colormap lookup turned a quarter turn, so the gradient runs vertically
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([0.0, -0.4])
+                    .with_scale([0.5, 0.4])
+                    .with_colormap_rotation(std::f32::consts::FRAC_PI_2),
+            );
+        });
+    });
+}
+
+fn sheared_stipple<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple_with_texture(&assets.mask, &assets.texture, |stipples| {
+            // skewed along x, then rotated
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([-0.4, 0.0])
+                    .with_scale([0.4, 0.4])
+                    .with_rotation(0.3)
+                    .with_transform([[1.0, 0.8, 0.0], [0.0, 1.0, 0.0]]),
+            );
+            // a full affine placement, with an offset in stipple space
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([0.4, 0.0])
+                    .with_scale([0.4, 0.4])
+                    .with_transform([[0.6, 0.0, 0.0], [-0.5, 1.2, -0.3]]),
+            );
+        });
+    });
+}
+
+fn blend_modes<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            stipples.draw(Stipple::default().with_scale([0.9, 0.6]));
+        });
+    });
+
+    // a red stipple in each mode, across the gradient and onto the paper
+    let modes = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Lighten,
+        BlendMode::Darken,
+    ];
+
+    canvas.layer(&assets.red, |layer| {
+        for (i, mode) in modes.iter().enumerate() {
+            layer.stipple(&assets.mask, |stipples| {
+                stipples.set_blend_mode(*mode);
+                stipples.draw(
+                    Stipple::default()
+                        .with_translation([-0.8 + 0.4 * i as f32, 0.3 - 0.3 * i as f32])
+                        .with_scale([0.25, 0.35]),
+                );
+            });
+        }
+    });
+}
+
+fn layer_opacity<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            stipples.draw(Stipple::default().with_scale([0.9, 0.6]));
+        });
+    });
+
+    // overlapping stipples, faded as a unit
+    canvas.layer(&assets.red, |layer| {
+        layer.set_opacity(0.5);
+        layer.stipple(&assets.mask, |stipples| {
+            for i in 0..3 {
+                stipples.draw(
+                    Stipple::default()
+                        .with_translation([-0.6 + 0.2 * i as f32, 0.2])
+                        .with_scale([0.3, 0.4]),
+                );
+            }
+        });
+    });
+
+    // a gradient glaze, multiplied onto the canvas
+    canvas.layer(&assets.gradient, |layer| {
+        layer.set_opacity(0.8);
+        layer.set_blend_mode(BlendMode::Multiply);
+        layer.stipple(&assets.mask, |stipples| {
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([0.4, -0.3])
+                    .with_scale([0.4, 0.5])
+                    .with_colormap_rotation(std::f32::consts::PI),
+            );
+        });
+    });
+}
+
+fn clipped_layers<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    // the same brush strokes, painted with different colormaps on the subject and background
+    for (colormap, clip) in [
+        (&assets.gradient, &assets.background),
+        (&assets.red, &assets.subject),
+    ]
+    .iter()
+    {
+        canvas.layer_with_clip(colormap, clip, |layer| {
+            layer.stipple(&assets.mask, |stipples| {
+                for i in 0..5 {
+                    stipples.draw(
+                        Stipple::default()
+                            .with_translation([-0.8 + 0.4 * i as f32, 0.0])
+                            .with_scale([0.4, 1.2]),
+                    );
+                }
+            });
+        });
+    }
+}
+
+fn toned_ground<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    canvas.set_background(Background::Color([0.9, 0.8, 0.6, 1.0]));
+    canvas.layer(&assets.gradient, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            stipples.draw(Stipple::default().with_scale([0.5, 0.5]));
+        });
+    });
+}
+
+fn image_ground<'a, B: DaliBackend>(canvas: &mut CanvasGate<'a, B>, assets: &'a Assets<B>) {
+    // the left edge of the image is translucent, so the paper shows through
+    canvas.set_background(Background::Image(&assets.ground));
+    canvas.layer(&assets.red, |layer| {
+        layer.stipple(&assets.mask, |stipples| {
+            stipples.draw(Stipple::default().with_scale([0.5, 0.5]));
+        });
+    });
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("gltests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/gltests")
+}
+
+/// Renders each scene with the reference backend, and overwrites the golden images
+pub fn update_goldens<B: DaliBackend>(pipeline: &mut DaliPipeline<B>) {
+    let assets = assets(pipeline);
+    create_dir_all(golden_dir()).expect("Could not create golden directory");
+
+    for (name, scene) in scenes::<B>() {
+        let actual = pipeline.render_canvas(SIZE, |canvas| scene(canvas, &assets));
+        let golden_path = golden_dir().join(format!("{}.png", name));
+        actual
+            .save(&golden_path)
+            .expect("Could not save golden image");
+        println!("golden {}: updated {}", name, golden_path.display());
+    }
+}
+
+/// Renders each scene, and compares it to the golden image.
+/// Returns the names of the scenes which didn't match.
+pub fn check_scenes<B: DaliBackend>(
+    backend_name: &str,
+    pipeline: &mut DaliPipeline<B>,
+) -> Vec<String> {
+    let assets = assets(pipeline);
+    let mut failures = Vec::new();
+
+    for (name, scene) in scenes::<B>() {
+        let actual = pipeline.render_canvas(SIZE, |canvas| scene(canvas, &assets));
+        let golden_path = golden_dir().join(format!("{}.png", name));
+
+        let expected = match image::open(&golden_path) {
+            Ok(image) => image.to_rgba(),
+            Err(e) => {
+                println!(
+                    "golden {} ({}): missing golden image: {}",
+                    name, backend_name, e
+                );
+                failures.push(format!("{} ({})", name, backend_name));
+                continue;
+            }
+        };
+
+        let comparison = compare(&expected, &actual);
+        if comparison.matches() {
+            println!("golden {} ({}): ok", name, backend_name);
+            continue;
+        }
+
+        let dir = output_dir();
+        create_dir_all(&dir).expect("Could not create output directory");
+        let actual_path = dir.join(format!("{}-{}-actual.png", name, backend_name));
+        let diff_path = dir.join(format!("{}-{}-diff.png", name, backend_name));
+        actual
+            .save(&actual_path)
+            .expect("Could not save actual image");
+        comparison
+            .diff
+            .save(&diff_path)
+            .expect("Could not save diff image");
+
+        println!(
+            "golden {} ({}): {} of {} pixels differ, max difference {:.1}.  See {}",
+            name,
+            backend_name,
+            comparison.different_pixels,
+            SIZE[0] * SIZE[1],
+            comparison.max_diff,
+            diff_path.display()
+        );
+        failures.push(format!("{} ({})", name, backend_name));
+    }
+
+    failures
+}
+
+struct Comparison {
+    different_pixels: u32,
+    max_diff: f32,
+    total_pixels: u32,
+    diff: RgbaImage,
+}
+
+impl Comparison {
+    fn matches(&self) -> bool {
+        let allowed = (self.total_pixels as f32 * MAX_DIFFERENT_PIXELS).floor() as u32;
+        self.different_pixels <= allowed && self.max_diff <= MAX_PIXEL_DIFF
+    }
+}
+
+/// Compares images with a luma-weighted channel difference, which is more sensitive to changes in lightness than hue.
+/// The diff image shows the expected image in grey, with differing pixels in red.
+fn compare(expected: &RgbaImage, actual: &RgbaImage) -> Comparison {
+    let total_pixels = SIZE[0] * SIZE[1];
+    let mut diff = RgbaImage::new(SIZE[0], SIZE[1]);
+
+    if expected.dimensions() != actual.dimensions() {
+        return Comparison {
+            different_pixels: total_pixels,
+            max_diff: 255.0,
+            total_pixels,
+            diff,
+        };
+    }
+
+    let mut different_pixels = 0;
+    let mut max_diff: f32 = 0.0;
+    for (x, y, e) in expected.enumerate_pixels() {
+        let a = actual.get_pixel(x, y);
+        let delta = |c: usize| (e.0[c] as f32 - a.0[c] as f32).abs();
+        let pixel_diff = 0.299 * delta(0) + 0.587 * delta(1) + 0.114 * delta(2);
+        max_diff = max_diff.max(pixel_diff);
+
+        let grey =
+            (0.299 * e.0[0] as f32 + 0.587 * e.0[1] as f32 + 0.114 * e.0[2] as f32) as u8 / 2 + 64;
+        let pixel = if pixel_diff > PIXEL_TOLERANCE {
+            different_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([grey, grey, grey, 255])
+        };
+        diff.put_pixel(x, y, pixel);
+    }
+
+    Comparison {
+        different_pixels,
+        max_diff,
+        total_pixels,
+        diff,
+    }
+}
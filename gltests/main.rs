@@ -1,8 +1,267 @@
-use dali::DaliContext;
+mod golden;
+
+use dali::texture_renderers::FragmentShaderRenderer;
+use dali::{
+    CanvasGate, DaliBackend, DaliContext, DaliError, DaliPipeline, Scene, SoftwareRenderer, Stipple,
+};
+use dali::{ColormapHandle, DaliPipelineHeadless, ImageFileFormat, MaskHandle, TextureHandle};
 
 /// GlfwSurface must be initialized from the main thread.
 /// Unfortuantely, Rust tests cannot be forced to run on the main thread
 /// So this workaround tests that the Context can be created
 pub fn main() {
-    let context = DaliContext::new();
-}
\ No newline at end of file
+    let mut context = DaliContext::new();
+
+    // luminance allows one graphics state per thread, so the tests share a pipeline
+    let mut pipeline = context.headless_pipeline();
+
+    headless_render(&mut pipeline);
+    software_matches_gpu(&mut pipeline);
+    streamed_render(&mut pipeline);
+    repeated_previews(&mut pipeline);
+    tiled_render(&mut pipeline);
+    file_render(&mut pipeline);
+
+    if std::env::var("DALI_UPDATE_GOLDEN").is_ok() {
+        golden::update_goldens(&mut pipeline);
+    }
+
+    let mut failures = golden::check_scenes("gl", &mut pipeline);
+    let mut software = DaliPipeline::new(SoftwareRenderer::new());
+    failures.extend(golden::check_scenes("software", &mut software));
+    assert!(failures.is_empty(), "golden images differ: {:?}", failures);
+}
+
+/// Renders a single red stipple without a window, and checks that it landed in the center of the canvas
+fn headless_render(pipeline: &mut DaliPipelineHeadless) {
+    // a bad shader is reported, and the pipeline is still usable afterwards
+    let bad_shader = FragmentShaderRenderer::new("not glsl", 16, 1);
+    match pipeline.try_texture(&bad_shader) {
+        Err(DaliError::Program(_)) => {}
+        Err(e) => panic!("Expected a program error, got {}", e),
+        Ok(_) => panic!("Expected a program error"),
+    }
+
+    let colormap = pipeline.colormap([4, 4], |_x, _y| [1.0, 0.0, 0.0, 1.0]);
+    let mask = pipeline.mask_from_image(image::GrayImage::from_pixel(8, 8, image::Luma([255])), 1);
+
+    let image = pipeline.render_canvas([32, 32], |canvas_gate| {
+        canvas_gate.layer(&colormap, |layer_gate| {
+            layer_gate.stipple(&mask, |stipple_gate| {
+                stipple_gate.draw(Stipple::default().with_scale([0.5, 0.5]));
+            });
+        });
+    });
+
+    assert_eq!((32, 32), image.dimensions());
+    assert_eq!(&[255, 0, 0, 255], &image.get_pixel(16, 16).0);
+    assert_eq!(&[255, 255, 255, 255], &image.get_pixel(1, 1).0);
+    println!("headless_render: ok");
+}
+
+fn gradient(x: f32, y: f32) -> [f32; 4] {
+    [x, y, 1.0 - x, 1.0]
+}
+
+fn radial_mask() -> image::GrayImage {
+    image::GrayImage::from_fn(32, 32, |x, y| {
+        let dx = x as f32 - 15.5;
+        let dy = y as f32 - 15.5;
+        let len = (dx * dx + dy * dy).sqrt() / 16.0;
+        image::Luma([(255.0 * (1.0 - len).max(0.0)) as u8])
+    })
+}
+
+fn stripes() -> image::GrayImage {
+    image::GrayImage::from_fn(32, 32, |x, y| image::Luma([((x + y) * 8 % 256) as u8]))
+}
+
+/// Draws stipples that cover rotation, aspect correction, colormap scale, gamma and textures
+fn scene<'a, B: DaliBackend>(
+    canvas: &mut CanvasGate<'a, B>,
+    colormap: &'a ColormapHandle<B>,
+    mask: &'a MaskHandle<B>,
+    texture: &'a TextureHandle<B>,
+) {
+    canvas.layer(colormap, |layer| {
+        layer.stipple(mask, |stipples| {
+            stipples.draw(Stipple::default().with_scale([0.6, 0.3]).with_rotation(0.7));
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([-0.5, 0.4])
+                    .with_scale([0.4, 0.4])
+                    .with_colormap_scale([2.0, 0.5])
+                    .with_gamma(2.0),
+            );
+        });
+
+        layer.stipple_with_texture(mask, texture, |stipples| {
+            stipples.draw(
+                Stipple::default()
+                    .with_translation([0.5, -0.4])
+                    .with_scale([0.5, 0.5])
+                    .with_texture_rotation(0.4),
+            );
+        });
+    });
+}
+
+/// Renders the same scene on the GPU and with the software renderer, and checks that they agree
+fn software_matches_gpu(pipeline: &mut DaliPipelineHeadless) {
+    let size = [96, 64];
+
+    let colormap = pipeline.colormap([16, 16], gradient);
+    let mask = pipeline.mask_from_image(radial_mask(), 4);
+    let texture = pipeline.texture_from_image(stripes(), 4);
+    let gpu = pipeline.render_canvas(size, |canvas| scene(canvas, &colormap, &mask, &texture));
+
+    let mut software = DaliPipeline::new(SoftwareRenderer::new());
+    let colormap = software.colormap([16, 16], gradient);
+    let mask = software.mask_from_image(radial_mask(), 4);
+    let texture = software.texture_from_image(stripes(), 4);
+    let cpu = software.render_canvas(size, |canvas| scene(canvas, &colormap, &mask, &texture));
+
+    let mut max_diff = 0;
+    let mut total_diff = 0u64;
+    for (a, b) in gpu.pixels().zip(cpu.pixels()) {
+        for c in 0..4 {
+            let diff = (a.0[c] as i32 - b.0[c] as i32).abs();
+            max_diff = max_diff.max(diff);
+            total_diff += diff as u64;
+        }
+    }
+    let mean_diff = total_diff as f64 / (size[0] * size[1] * 4) as f64;
+
+    assert!(cpu.pixels().any(|p| p.0 != [255, 255, 255, 255]));
+    assert!(max_diff <= 2, "max channel difference was {}", max_diff);
+    assert!(
+        mean_diff < 0.05,
+        "mean channel difference was {}",
+        mean_diff
+    );
+    println!(
+        "software_matches_gpu: ok (max diff {}, mean diff {:.4})",
+        max_diff, mean_diff
+    );
+}
+
+/// Streams more stipples than fit in one instance chunk, and checks that the output matches a buffered render
+fn streamed_render(pipeline: &mut DaliPipelineHeadless) {
+    let size = [96, 64];
+    let colormap = pipeline.colormap([16, 16], gradient);
+    let mask = pipeline.mask_from_image(radial_mask(), 4);
+
+    let stipple = |i: usize| {
+        let t = i as f32 / 1500.0;
+        Stipple::default()
+            .with_translation([t * 2.0 - 1.0, (t * 23.0).sin() * 0.8])
+            .with_scale([0.08, 0.08])
+            .with_rotation(t * 9.0)
+    };
+
+    let buffered = pipeline.render_canvas(size, |canvas| {
+        canvas.layer(&colormap, |layer| {
+            layer.stipple(&mask, |stipples| {
+                (0..1500).for_each(|i| stipples.draw(stipple(i)))
+            });
+        });
+    });
+
+    let streamed = pipeline.stream_canvas(size, |canvas| {
+        canvas.layer(&colormap, |layer| {
+            layer.stipple(&mask, |stipples| {
+                (0..1500).for_each(|i| stipples.draw(stipple(i)))
+            });
+        });
+    });
+
+    assert!(buffered
+        .pixels()
+        .zip(streamed.pixels())
+        .all(|(a, b)| a == b));
+    println!("streamed_render: ok");
+}
+
+/// Renders many small previews, as a parameter sweep does, and checks that they're identical.
+/// Programs and tessellations are built once, so the time per render is mostly drawing.
+fn repeated_previews(pipeline: &mut DaliPipelineHeadless) {
+    let colormap = pipeline.colormap([16, 16], gradient);
+    let mask = pipeline.mask_from_image(radial_mask(), 4);
+
+    let render = |pipeline: &mut DaliPipelineHeadless| {
+        pipeline.render_canvas([32, 32], |canvas| {
+            canvas.layer(&colormap, |layer| {
+                layer.stipple(&mask, |stipples| {
+                    stipples.draw(Stipple::default().with_scale([0.5, 0.5]));
+                });
+            });
+        })
+    };
+
+    let first = render(pipeline);
+    let renders = 200;
+    let start = std::time::Instant::now();
+    for _ in 0..renders {
+        assert_eq!(first.to_vec(), render(pipeline).to_vec());
+    }
+
+    let per_render = start.elapsed().as_secs_f64() * 1000.0 / renders as f64;
+    println!(
+        "repeated_previews: ok ({} renders, {:.2} ms per render)",
+        renders, per_render
+    );
+}
+
+/// Renders a canvas in tiles, and checks that stipples crossing tile boundaries line up with a whole render
+fn tiled_render(pipeline: &mut DaliPipelineHeadless) {
+    let size = [150, 100];
+    let colormap = pipeline.colormap([16, 16], gradient);
+    let mask = pipeline.mask_from_image(radial_mask(), 4);
+    let texture = pipeline.texture_from_image(stripes(), 4);
+
+    let scene = Scene::from_fn(|canvas| {
+        scene(canvas, &colormap, &mask, &texture);
+        canvas.layer(&colormap, |layer| {
+            layer.set_opacity(0.6);
+            layer.stipple(&mask, |stipples| {
+                stipples.draw(
+                    Stipple::default()
+                        .with_scale([0.9, 0.2])
+                        .with_rotation(-0.5),
+                );
+            });
+        });
+    });
+
+    let whole = pipeline.render_scene(size, &scene);
+    let tiled = pipeline.render_scene_tiled(size, [64, 48], &scene);
+
+    let max_diff = whole
+        .pixels()
+        .zip(tiled.pixels())
+        .flat_map(|(a, b)| (0..4).map(move |c| (a.0[c] as i32 - b.0[c] as i32).abs()))
+        .max()
+        .unwrap_or(0);
+    assert!(max_diff <= 1, "max channel difference was {}", max_diff);
+    println!("tiled_render: ok (max diff {})", max_diff);
+}
+
+/// Renders a canvas to a PNG file, encoded on a background thread, and checks that it matches an in-memory render
+fn file_render(pipeline: &mut DaliPipelineHeadless) {
+    let size = [300, 200];
+    let colormap = pipeline.colormap([16, 16], gradient);
+    let mask = pipeline.mask_from_image(radial_mask(), 4);
+    let texture = pipeline.texture_from_image(stripes(), 4);
+    let scene = Scene::from_fn(|canvas| scene(canvas, &colormap, &mask, &texture));
+
+    let path = std::env::temp_dir().join(format!("dali-gltests-{}.png", std::process::id()));
+    pipeline.render_scene_to_file(size, &path, ImageFileFormat::Png, &scene);
+    let decoded = image::open(&path)
+        .expect("Should have decoded file")
+        .to_rgba();
+    std::fs::remove_file(&path).expect("Should have removed file");
+
+    let expected = pipeline.render_scene(size, &scene);
+    assert!(expected.to_vec() == decoded.to_vec());
+    println!("file_render: ok");
+}
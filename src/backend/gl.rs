@@ -0,0 +1,542 @@
+use luminance::blending::{Equation, Factor};
+use luminance::context::GraphicsContext;
+use luminance::depth_test::DepthComparison;
+use luminance::framebuffer::{ColorSlot, Framebuffer};
+use luminance::pipeline::{BoundTexture, Pipeline, ShadingGate};
+use luminance::pixel::{Floating, R32F, RGBA32F};
+use luminance::render_state::RenderState;
+use luminance::shader::program::Program;
+use luminance::tess::{Mode, Tess, TessBuilder, TessSlice};
+use luminance::texture::{Dim2, Flat, GenMipmaps, MagFilter, MinFilter, Sampler, Texture, Wrap};
+
+use crate::backend::{BackendFrame, DaliBackend, FrameCallback, Tile, INSTANCE_CHUNK_SIZE};
+use crate::blend::BlendMode;
+use crate::error::DaliError;
+use crate::render::semantics::composite::{self, CompositeInterface, CompositeSemantics};
+use crate::render::semantics::stipple::{self, StippleInterface, StippleSemantics};
+use crate::Stipple;
+
+/// Renders with an OpenGL context, such as a [WindowSurface](crate::WindowSurface), `GlfwSurface` or [HeadlessSurface](crate::HeadlessSurface)
+pub struct GlBackend<C> {
+    // programs and tessellations must be dropped before the context that owns them
+    pub(crate) resources: Option<GlResources>,
+    pub(crate) context: C,
+}
+
+impl<C> GlBackend<C> {
+    pub fn new(context: C) -> GlBackend<C> {
+        GlBackend {
+            resources: None,
+            context,
+        }
+    }
+
+    /// The OpenGL context used for rendering
+    pub fn context(&self) -> &C {
+        &self.context
+    }
+
+    /// The OpenGL context used for rendering
+    pub fn context_mut(&mut self) -> &mut C {
+        &mut self.context
+    }
+}
+
+/// An offscreen render target for [GlBackend]
+pub struct GlTarget {
+    pub(crate) framebuffer: Framebuffer<Flat, Dim2, RGBA32F, ()>,
+    // the OpenGL name of the framebuffer, which luminance doesn't expose.  It's recorded by each render
+    handle: u32,
+}
+
+pub(crate) fn colormap_sampler() -> Sampler {
+    // the colormap is likely to be smaller than the output image size (on print quality images)
+    // so the mag filter really needs interpolation.
+    Sampler {
+        min_filter: MinFilter::LinearMipmapLinear,
+        mag_filter: MagFilter::Linear,
+        wrap_s: Wrap::MirroredRepeat,
+        wrap_t: Wrap::MirroredRepeat,
+        ..Sampler::default()
+    }
+}
+
+pub(crate) fn texture_sampler() -> Sampler {
+    // we want all the interpolation we can get.
+    // this will prevent small blocky areas in the output
+    Sampler {
+        min_filter: MinFilter::LinearMipmapLinear,
+        mag_filter: MagFilter::Linear,
+        ..Sampler::default()
+    }
+}
+
+impl<C: GraphicsContext> DaliBackend for GlBackend<C> {
+    type Colormap = Texture<Flat, Dim2, RGBA32F>;
+    type Mask = Texture<Flat, Dim2, R32F>;
+    type Texture = Texture<Flat, Dim2, R32F>;
+    type Target = GlTarget;
+
+    fn upload_colormap(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+    ) -> Result<Self::Colormap, DaliError> {
+        // TODO: look at samplers.
+        let texture = Texture::new(&mut self.context, size, 0, colormap_sampler())?;
+        texture.upload_raw(GenMipmaps::No, texels)?;
+        Ok(texture)
+    }
+
+    fn upload_mask(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+        mipmaps: usize,
+    ) -> Result<Self::Mask, DaliError> {
+        let texture = Texture::new(&mut self.context, size, mipmaps, texture_sampler())?;
+        texture.upload_raw(GenMipmaps::Yes, texels)?;
+        Ok(texture)
+    }
+
+    fn upload_texture(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+        mipmaps: usize,
+    ) -> Result<Self::Texture, DaliError> {
+        self.upload_mask(size, texels, mipmaps)
+    }
+
+    fn max_target_size(&self) -> u32 {
+        let mut texture = 0;
+        let mut viewport = [0; 2];
+        unsafe {
+            gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut texture);
+            gl::GetIntegerv(gl::MAX_VIEWPORT_DIMS, viewport.as_mut_ptr());
+        }
+        texture.min(viewport[0]).min(viewport[1]).max(1) as u32
+    }
+
+    fn create_target(&mut self, size: [u32; 2]) -> Result<Self::Target, DaliError> {
+        Ok(GlTarget {
+            framebuffer: Framebuffer::new(&mut self.context, size, 0)?,
+            handle: 0,
+        })
+    }
+
+    fn render_frame(
+        &mut self,
+        target: &mut Self::Target,
+        tile: Tile,
+        clear_color: [f32; 4],
+        draw: &mut FrameCallback<'_, Self>,
+    ) -> Result<(), DaliError> {
+        let mut handle = 0;
+        render_frame(
+            &mut self.context,
+            &mut self.resources,
+            &target.framebuffer,
+            tile,
+            clear_color,
+            &mut |frame| {
+                // luminance binds the target for drawing, so its name is read back for read_target_rows
+                unsafe { gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut handle) };
+                draw(frame)
+            },
+        )?;
+        target.handle = handle as u32;
+        Ok(())
+    }
+
+    fn read_target(&mut self, target: &Self::Target) -> Result<Vec<f32>, DaliError> {
+        Ok(target.framebuffer.color_slot().get_raw_texels())
+    }
+
+    fn read_target_rows(
+        &mut self,
+        target: &Self::Target,
+        first: u32,
+        rows: u32,
+    ) -> Result<Vec<f32>, DaliError> {
+        if target.handle == 0 {
+            return Err(DaliError::Surface(
+                "the target must be rendered before its rows are read".to_string(),
+            ));
+        }
+
+        let width = target.framebuffer.width();
+        let mut texels = vec![0.0f32; (width * rows) as usize * 4];
+        unsafe {
+            let mut previous = 0;
+            gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target.handle);
+            gl::ReadPixels(
+                0,
+                first as i32,
+                width as i32,
+                rows as i32,
+                gl::RGBA,
+                gl::FLOAT,
+                texels.as_mut_ptr() as *mut std::ffi::c_void,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous as u32);
+        }
+        Ok(texels)
+    }
+}
+
+/// Shader programs and tessellations, which are built on the first render, and reused by every render after it
+pub(crate) struct GlResources {
+    stipple_program: Program<StippleSemantics, (), StippleInterface>,
+    stipple_texture_program: Program<StippleSemantics, (), StippleInterface>,
+    // a quad, with a chunk of instances which is overwritten for each draw
+    tess: Tess,
+    composite_program: Program<CompositeSemantics, (), CompositeInterface>,
+    composite_tess: Tess,
+    // a copy of the target, which lighten and darken read the canvas from.  It's resized with the target.
+    backdrop: Option<Texture<Flat, Dim2, RGBA32F>>,
+}
+
+impl GlResources {
+    fn new<C: GraphicsContext>(context: &mut C) -> Result<GlResources, DaliError> {
+        const QUAD: [[f32; 2]; 4] = [[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]];
+
+        let null_instance: stipple::VertexInstance = Stipple::new().with_scale([0.0, 0.0]).into();
+        let null_instances = vec![null_instance; INSTANCE_CHUNK_SIZE];
+
+        let stipple_quad: Vec<stipple::Vertex> = QUAD
+            .iter()
+            .copied()
+            .map(stipple::Vertex::new_with_position)
+            .collect();
+
+        let tess = TessBuilder::new(context)
+            .add_vertices(&stipple_quad)
+            .add_instances(null_instances)
+            .set_mode(Mode::TriangleStrip)
+            .build()?;
+
+        let composite_quad: Vec<composite::Vertex> = QUAD
+            .iter()
+            .copied()
+            .map(composite::Vertex::new_with_position)
+            .collect();
+
+        let composite_tess = TessBuilder::new(context)
+            .add_vertices(&composite_quad)
+            .set_mode(Mode::TriangleStrip)
+            .build()?;
+
+        Ok(GlResources {
+            stipple_program: stipple::compile()?,
+            stipple_texture_program: stipple::compile_with_texture()?,
+            tess,
+            composite_program: composite::compile()?,
+            composite_tess,
+            backdrop: None,
+        })
+    }
+}
+
+/// Renders a frame into any framebuffer, including the back buffer of a window
+pub(crate) fn render_frame<C, CS>(
+    context: &mut C,
+    resources: &mut Option<GlResources>,
+    target: &Framebuffer<Flat, Dim2, CS, ()>,
+    tile: Tile,
+    clear_color: [f32; 4],
+    draw: &mut FrameCallback<'_, GlBackend<C>>,
+) -> Result<(), DaliError>
+where
+    C: GraphicsContext,
+    CS: ColorSlot<Flat, Dim2>,
+{
+    if resources.is_none() {
+        *resources = Some(GlResources::new(context)?);
+    }
+    let resources = resources.as_mut().expect("Resources were just built");
+
+    if resources.backdrop.as_ref().map(|backdrop| backdrop.size()) != Some(tile.size) {
+        resources.backdrop = Some(Texture::new(context, tile.size, 0, Sampler::default())?);
+    }
+
+    // errors inside the pipeline are captured here, and stop the render
+    let mut result = Ok(());
+
+    debug_assert_eq!([target.width(), target.height()], tile.size);
+    let aspect = tile.canvas[0] as f32 / tile.canvas[1] as f32;
+    let tile_transform = tile_transform(tile);
+    context
+        .pipeline_builder()
+        .pipeline(target, clear_color, |pipeline, mut shd_gate| {
+            let mut frame = GlFrame {
+                pipeline: &pipeline,
+                shd_gate: &mut shd_gate,
+                stipple_program: &resources.stipple_program,
+                stipple_texture_program: &resources.stipple_texture_program,
+                tess: &mut resources.tess,
+                composite_program: &resources.composite_program,
+                composite_tess: &resources.composite_tess,
+                backdrop: resources
+                    .backdrop
+                    .as_ref()
+                    .expect("Backdrop was just built"),
+                aspect,
+                tile_size: tile.size,
+                tile_transform,
+            };
+
+            result = draw(&mut frame);
+        });
+
+    result
+}
+
+/// The scale and offset from canvas coordinates to the coordinates of the tile, for stipple-vs.glsl
+fn tile_transform(tile: Tile) -> [f32; 4] {
+    let canvas = [tile.canvas[0] as f32, tile.canvas[1] as f32];
+    let size = [tile.size[0] as f32, tile.size[1] as f32];
+    let origin = tile.origin();
+    let origin = [origin[0] as f32, origin[1] as f32];
+
+    // maps the tile's pixels on the canvas to -1..1
+    [
+        canvas[0] / size[0],
+        canvas[1] / size[1],
+        (canvas[0] - 2.0 * origin[0] - size[0]) / size[0],
+        (canvas[1] - 2.0 * origin[1] - size[1]) / size[1],
+    ]
+}
+
+/// The fixed-function blending for a blend mode.  The shader output is selected by [BlendMode::output]
+fn blending(blend_mode: BlendMode) -> (Equation, Factor, Factor) {
+    match blend_mode {
+        BlendMode::Normal => (Equation::Additive, Factor::One, Factor::SrcAlphaComplement),
+        BlendMode::Multiply => (Equation::Additive, Factor::DestColor, Factor::Zero),
+        BlendMode::Screen => (Equation::Additive, Factor::One, Factor::SrcColorComplement),
+        BlendMode::Lighten => (Equation::Max, Factor::One, Factor::One),
+        BlendMode::Darken => (Equation::Min, Factor::One, Factor::One),
+        BlendMode::EraseAlpha => (Equation::Additive, Factor::One, Factor::SrcAlphaComplement),
+    }
+}
+
+/// Applies the blending which luminance can't express, because alpha blends differently than color.
+/// This runs inside a render gate, after luminance has applied [blending]
+fn separate_blending(blend_mode: BlendMode) {
+    match blend_mode {
+        // darken keeps the larger coverage, like lighten
+        BlendMode::Darken => unsafe { gl::BlendEquationSeparate(gl::MIN, gl::MAX) },
+        // erase moves the color toward white paper, and removes the coverage
+        BlendMode::EraseAlpha => unsafe {
+            gl::BlendFuncSeparate(
+                gl::ONE,
+                gl::ONE_MINUS_SRC_ALPHA,
+                gl::ZERO,
+                gl::ONE_MINUS_SRC_ALPHA,
+            )
+        },
+        _ => {}
+    }
+}
+
+/// Restores the blending applied by [blending], which luminance caches and won't apply again
+fn restore_blending(blend_mode: BlendMode) {
+    match blend_mode {
+        BlendMode::Darken => unsafe { gl::BlendEquation(gl::MIN) },
+        BlendMode::EraseAlpha => unsafe { gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA) },
+        _ => {}
+    }
+}
+
+/// Copies the framebuffer being drawn into the texture bound to the active texture unit.
+/// The copy is a blit, which resolves the multisampled back buffer of a window preview
+fn copy_target(size: [u32; 2]) {
+    let (width, height) = (size[0] as i32, size[1] as i32);
+    unsafe {
+        // luminance only binds framebuffers for drawing, so the target is bound for reading here
+        let mut framebuffer = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut framebuffer);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer as u32);
+
+        // the backdrop is attached to a single-sample framebuffer, which receives the resolved target
+        let mut texture = 0;
+        gl::GetIntegerv(gl::TEXTURE_BINDING_2D, &mut texture);
+        let mut resolve = 0;
+        gl::GenFramebuffers(1, &mut resolve);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, resolve);
+        gl::FramebufferTexture2D(
+            gl::DRAW_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture as u32,
+            0,
+        );
+        gl::BlitFramebuffer(
+            0,
+            0,
+            width,
+            height,
+            0,
+            0,
+            width,
+            height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+
+        // luminance caches the draw framebuffer, so the target is bound again
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer as u32);
+        gl::DeleteFramebuffers(1, &resolve);
+    }
+}
+
+struct GlFrame<'f, 'p, C> {
+    pipeline: &'f Pipeline<'p>,
+    shd_gate: &'f mut ShadingGate<'p, C>,
+    stipple_program: &'f Program<StippleSemantics, (), StippleInterface>,
+    stipple_texture_program: &'f Program<StippleSemantics, (), StippleInterface>,
+    tess: &'f mut Tess,
+    composite_program: &'f Program<CompositeSemantics, (), CompositeInterface>,
+    composite_tess: &'f Tess,
+    backdrop: &'f Texture<Flat, Dim2, RGBA32F>,
+    aspect: f32,
+    tile_size: [u32; 2],
+    tile_transform: [f32; 4],
+}
+
+impl<'f, 'p, C> GlFrame<'f, 'p, C> {
+    /// Copies the canvas into the backdrop, and binds it, if the blend mode reads the canvas
+    fn bind_backdrop(
+        &self,
+        blend_mode: BlendMode,
+    ) -> Option<BoundTexture<'f, Flat, Dim2, Floating>> {
+        if !blend_mode.output().reads_backdrop() {
+            return None;
+        }
+
+        // binding the backdrop makes its texture unit active, so it receives the copy
+        let bound = self.pipeline.bind_texture(self.backdrop);
+        copy_target(self.tile_size);
+        Some(bound)
+    }
+}
+
+impl<'f, 'p, C: GraphicsContext> BackendFrame<GlBackend<C>> for GlFrame<'f, 'p, C> {
+    fn draw_stipples(
+        &mut self,
+        colormap: &Texture<Flat, Dim2, RGBA32F>,
+        mask: &Texture<Flat, Dim2, R32F>,
+        texture: Option<&Texture<Flat, Dim2, R32F>>,
+        clip: Option<&Texture<Flat, Dim2, R32F>>,
+        blend_mode: BlendMode,
+        stipples: &[Stipple],
+    ) -> Result<(), DaliError> {
+        if stipples.is_empty() {
+            return Ok(());
+        }
+
+        let mut instances: Vec<stipple::VertexInstance> =
+            stipples.iter().map(|stipple| stipple.into()).collect();
+
+        let pipeline = self.pipeline;
+        let bound_backdrop = self.bind_backdrop(blend_mode);
+        let bound_mask = pipeline.bind_texture(mask);
+        let bound_colormap = pipeline.bind_texture(colormap);
+        let bound_texture = texture.map(|e| pipeline.bind_texture(e));
+        let bound_clip = clip.map(|e| pipeline.bind_texture(e));
+
+        let program = if bound_texture.is_some() {
+            self.stipple_texture_program
+        } else {
+            self.stipple_program
+        };
+
+        let aspect = self.aspect;
+        let tile_transform = self.tile_transform;
+        let tess = &mut *self.tess;
+        let mut result = Ok(());
+
+        self.shd_gate.shade(program, |iface, mut rdr_gate| {
+            let render_state = RenderState::default()
+                .set_blending(blending(blend_mode))
+                .set_depth_test(DepthComparison::Always);
+
+            rdr_gate.render(render_state, |mut tess_gate| {
+                separate_blending(blend_mode);
+                iface.aspect_ratio.update(aspect);
+                iface.tile_transform.update(tile_transform);
+                iface.mask.update(&bound_mask);
+                iface.colormap.update(&bound_colormap);
+                iface.discard_threshold.update(0.0f32);
+                iface.blend_output.update(blend_mode.output() as i32);
+
+                if let Some(tex) = &bound_texture {
+                    iface.texture.update(tex);
+                }
+
+                iface.clip_enabled.update(bound_clip.is_some());
+                if let Some(clip) = &bound_clip {
+                    iface.clip.update(clip);
+                }
+
+                if let Some(backdrop) = &bound_backdrop {
+                    iface.backdrop.update(backdrop);
+                }
+
+                for chunk in instances.chunks_mut(INSTANCE_CHUNK_SIZE) {
+                    match tess.as_inst_slice_mut() {
+                        Ok(mut slice) => slice[0..chunk.len()].swap_with_slice(chunk),
+                        Err(e) => {
+                            result = Err(e.into());
+                            break;
+                        }
+                    }
+
+                    if chunk.len() == INSTANCE_CHUNK_SIZE {
+                        tess_gate.render(&*tess);
+                    } else {
+                        let slice = TessSlice::inst_whole(&*tess, chunk.len());
+                        tess_gate.render(slice);
+                    }
+                }
+
+                restore_blending(blend_mode);
+            });
+        });
+
+        result
+    }
+
+    fn composite(
+        &mut self,
+        layer: &GlTarget,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> Result<(), DaliError> {
+        let bound_backdrop = self.bind_backdrop(blend_mode);
+        let bound_layer = self.pipeline.bind_texture(layer.framebuffer.color_slot());
+        let tess = self.composite_tess;
+
+        self.shd_gate
+            .shade(self.composite_program, |iface, mut rdr_gate| {
+                let render_state = RenderState::default()
+                    .set_blending(blending(blend_mode))
+                    .set_depth_test(DepthComparison::Always);
+
+                rdr_gate.render(render_state, |mut tess_gate| {
+                    separate_blending(blend_mode);
+                    iface.layer.update(&bound_layer);
+                    iface.opacity.update(opacity);
+                    iface.blend_output.update(blend_mode.output() as i32);
+                    if let Some(backdrop) = &bound_backdrop {
+                        iface.backdrop.update(backdrop);
+                    }
+                    tess_gate.render(tess);
+                    restore_blending(blend_mode);
+                });
+            });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,622 @@
+use crate::blend::BlendMode;
+use crate::error::DaliError;
+use crate::Stipple;
+
+pub use gl::{GlBackend, GlTarget};
+
+pub(crate) mod gl;
+
+/// Stipples are uploaded to the backend in chunks of this many instances
+pub(crate) const INSTANCE_CHUNK_SIZE: usize = 512;
+
+/// The region of the canvas that a render target covers.
+/// Large canvases are rendered in tiles, and every other render covers the whole canvas.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Tile {
+    /// The size of the whole canvas, in pixels
+    pub canvas: [u32; 2],
+    /// The top left corner of the tile, in canvas pixels
+    pub offset: [u32; 2],
+    /// The size of the tile, which matches the size of the render target
+    pub size: [u32; 2],
+}
+
+impl Tile {
+    /// A tile which covers the whole canvas
+    pub fn whole(size: [u32; 2]) -> Tile {
+        Tile {
+            canvas: size,
+            offset: [0, 0],
+            size,
+        }
+    }
+
+    /// The bottom left corner of the tile, in canvas pixels, counting rows from the bottom like OpenGL
+    pub fn origin(&self) -> [u32; 2] {
+        [
+            self.offset[0],
+            self.canvas[1] - self.offset[1] - self.size[1],
+        ]
+    }
+
+    /// Splits the canvas into rows of tiles, from the top left.  Tiles on the right and bottom edges may be smaller.
+    pub(crate) fn split(canvas: [u32; 2], tile_size: [u32; 2]) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        for y in (0..canvas[1]).step_by(tile_size[1].max(1) as usize) {
+            for x in (0..canvas[0]).step_by(tile_size[0].max(1) as usize) {
+                tiles.push(Tile {
+                    canvas,
+                    offset: [x, y],
+                    size: [
+                        tile_size[0].min(canvas[0] - x),
+                        tile_size[1].min(canvas[1] - y),
+                    ],
+                });
+            }
+        }
+        tiles
+    }
+}
+
+/// A rendering backend, which owns the resources bound by layers and stipples, and draws stipples into render targets.
+///
+/// [GlBackend] renders with OpenGL (in a window, or an offscreen context),
+/// and [SoftwareRenderer](crate::SoftwareRenderer) renders on the CPU.
+/// Handles and gates are generic over the backend, so a handle can only be drawn by the backend that created it.
+///
+/// Texels are passed as normalized f32 values, in row-major order, starting with the first row of the source image.
+pub trait DaliBackend: Sized {
+    type Colormap;
+    type Mask;
+    type Texture;
+    /// An offscreen RGBA render target, which stores the color over white paper, and the coverage in alpha
+    type Target;
+
+    /// Uploads an RGBA colormap, which is sampled with mirrored repeat
+    fn upload_colormap(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+    ) -> Result<Self::Colormap, DaliError>;
+
+    /// Uploads a greyscale mask, and generates `mipmaps` additional levels
+    fn upload_mask(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+        mipmaps: usize,
+    ) -> Result<Self::Mask, DaliError>;
+
+    /// Uploads a greyscale texture, and generates `mipmaps` additional levels
+    fn upload_texture(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+        mipmaps: usize,
+    ) -> Result<Self::Texture, DaliError>;
+
+    /// The largest width and height of a render target, such as the GPU's maximum texture size
+    fn max_target_size(&self) -> u32;
+
+    /// Allocates a render target with the given size
+    fn create_target(&mut self, size: [u32; 2]) -> Result<Self::Target, DaliError>;
+
+    /// Clears the target, and calls `draw` with a frame that draws stipples into the target.
+    /// The target covers the given tile of the canvas, and stipples are positioned relative to the whole canvas.
+    /// Stipples are blended in the order they are submitted.
+    fn render_frame(
+        &mut self,
+        target: &mut Self::Target,
+        tile: Tile,
+        clear_color: [f32; 4],
+        draw: &mut FrameCallback<'_, Self>,
+    ) -> Result<(), DaliError>;
+
+    /// Reads the RGBA texels of a target, starting with the bottom row
+    fn read_target(&mut self, target: &Self::Target) -> Result<Vec<f32>, DaliError>;
+
+    /// Reads `rows` rows of RGBA texels from a rendered target, starting with row `first` from the bottom
+    fn read_target_rows(
+        &mut self,
+        target: &Self::Target,
+        first: u32,
+        rows: u32,
+    ) -> Result<Vec<f32>, DaliError>;
+}
+
+/// Submits stipples to a frame, during [DaliBackend::render_frame]
+pub type FrameCallback<'a, B> = dyn FnMut(&mut dyn BackendFrame<B>) -> Result<(), DaliError> + 'a;
+
+/// Draws instanced stipples into a render target, during [DaliBackend::render_frame]
+pub trait BackendFrame<B: DaliBackend> {
+    /// Draws stipples with the given mask, colormap, (optional) texture, and (optional) canvas clip mask
+    fn draw_stipples(
+        &mut self,
+        colormap: &B::Colormap,
+        mask: &B::Mask,
+        texture: Option<&B::Texture>,
+        clip: Option<&B::Mask>,
+        blend_mode: BlendMode,
+        stipples: &[Stipple],
+    ) -> Result<(), DaliError>;
+
+    /// Composites an isolated layer, rendered into a target with the same size as the frame
+    fn composite(
+        &mut self,
+        layer: &B::Target,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> Result<(), DaliError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackendFrame, DaliBackend, FrameCallback, Tile, INSTANCE_CHUNK_SIZE};
+    use crate::error::DaliError;
+    use crate::testing;
+    use crate::{BlendMode, CancelToken, DaliPipeline, ImageFileFormat, Stipple};
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Op {
+        UploadColormap([u32; 2]),
+        UploadMask([u32; 2], usize),
+        UploadTexture([u32; 2], usize),
+        CreateTarget([u32; 2]),
+        RenderFrame([u32; 2]),
+        Draw(u32, u32, Option<u32>, Option<u32>, BlendMode, usize),
+        Composite([u32; 2], f32, BlendMode),
+        Read([u32; 2]),
+        ReadRows([u32; 2], u32, u32),
+    }
+
+    /// Records backend operations.  Resources are identified by the order they were uploaded.
+    #[derive(Default)]
+    struct RecordingBackend {
+        ops: Vec<Op>,
+        uploads: u32,
+        fail_targets: bool,
+        max_target_size: Option<u32>,
+    }
+
+    impl RecordingBackend {
+        fn upload(&mut self, op: Op) -> Result<u32, DaliError> {
+            self.ops.push(op);
+            self.uploads += 1;
+            Ok(self.uploads)
+        }
+    }
+
+    impl DaliBackend for RecordingBackend {
+        type Colormap = u32;
+        type Mask = u32;
+        type Texture = u32;
+        type Target = [u32; 2];
+
+        fn upload_colormap(&mut self, size: [u32; 2], texels: &[f32]) -> Result<u32, DaliError> {
+            assert_eq!((size[0] * size[1] * 4) as usize, texels.len());
+            self.upload(Op::UploadColormap(size))
+        }
+
+        fn upload_mask(
+            &mut self,
+            size: [u32; 2],
+            texels: &[f32],
+            mipmaps: usize,
+        ) -> Result<u32, DaliError> {
+            assert_eq!((size[0] * size[1]) as usize, texels.len());
+            self.upload(Op::UploadMask(size, mipmaps))
+        }
+
+        fn upload_texture(
+            &mut self,
+            size: [u32; 2],
+            texels: &[f32],
+            mipmaps: usize,
+        ) -> Result<u32, DaliError> {
+            assert_eq!((size[0] * size[1]) as usize, texels.len());
+            self.upload(Op::UploadTexture(size, mipmaps))
+        }
+
+        fn max_target_size(&self) -> u32 {
+            self.max_target_size.unwrap_or(u32::MAX)
+        }
+
+        fn create_target(&mut self, size: [u32; 2]) -> Result<[u32; 2], DaliError> {
+            if self.fail_targets {
+                return Err(DaliError::Surface("no targets".to_string()));
+            }
+
+            self.ops.push(Op::CreateTarget(size));
+            Ok(size)
+        }
+
+        fn render_frame(
+            &mut self,
+            target: &mut [u32; 2],
+            tile: Tile,
+            _clear_color: [f32; 4],
+            draw: &mut FrameCallback<'_, Self>,
+        ) -> Result<(), DaliError> {
+            assert_eq!(*target, tile.size);
+            self.ops.push(Op::RenderFrame(*target));
+            draw(self)
+        }
+
+        fn read_target(&mut self, target: &[u32; 2]) -> Result<Vec<f32>, DaliError> {
+            self.ops.push(Op::Read(*target));
+            Ok(vec![0.0; (target[0] * target[1] * 4) as usize])
+        }
+
+        fn read_target_rows(
+            &mut self,
+            target: &[u32; 2],
+            first: u32,
+            rows: u32,
+        ) -> Result<Vec<f32>, DaliError> {
+            assert!(first + rows <= target[1]);
+            self.ops.push(Op::ReadRows(*target, first, rows));
+            Ok(vec![0.0; (target[0] * rows * 4) as usize])
+        }
+    }
+
+    impl BackendFrame<RecordingBackend> for RecordingBackend {
+        fn draw_stipples(
+            &mut self,
+            colormap: &u32,
+            mask: &u32,
+            texture: Option<&u32>,
+            clip: Option<&u32>,
+            blend_mode: BlendMode,
+            stipples: &[Stipple],
+        ) -> Result<(), DaliError> {
+            self.ops.push(Op::Draw(
+                *colormap,
+                *mask,
+                texture.copied(),
+                clip.copied(),
+                blend_mode,
+                stipples.len(),
+            ));
+            Ok(())
+        }
+
+        fn composite(
+            &mut self,
+            layer: &[u32; 2],
+            opacity: f32,
+            blend_mode: BlendMode,
+        ) -> Result<(), DaliError> {
+            self.ops.push(Op::Composite(*layer, opacity, blend_mode));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn draws_layers_in_order() {
+        let mut pipeline = DaliPipeline::new(RecordingBackend::default());
+        let colormap = pipeline.colormap([2, 3], |_x, _y| [0.0; 4]);
+        let mask = pipeline.mask_from_image(image::GrayImage::new(6, 4), 2);
+        let texture = pipeline.texture_from_image(image::GrayImage::new(4, 4), 1);
+
+        let image = pipeline.render_canvas([8, 4], |canvas| {
+            canvas.layer(&colormap, |layer| {
+                layer.stipple(&mask, |stipples| {
+                    stipples.draw(Stipple::default());
+                    stipples.draw(Stipple::default());
+                });
+                layer.stipple_with_texture(&mask, &texture, |stipples| {
+                    stipples.set_blend_mode(BlendMode::Multiply);
+                    stipples.draw(Stipple::default());
+                });
+            });
+        });
+
+        assert_eq!((8, 4), image.dimensions());
+        assert_eq!(
+            vec![
+                Op::UploadColormap([2, 3]),
+                Op::UploadMask([6, 6], 2),
+                Op::UploadTexture([4, 4], 1),
+                Op::CreateTarget([8, 4]),
+                Op::RenderFrame([8, 4]),
+                Op::Draw(1, 2, None, None, BlendMode::Normal, 2),
+                Op::Draw(1, 2, Some(3), None, BlendMode::Multiply, 1),
+                Op::Read([8, 4]),
+            ],
+            pipeline.backend().ops
+        );
+    }
+
+    #[test]
+    fn composites_isolated_layers() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(RecordingBackend::default(), |_x, _y| [0.0; 4]);
+
+        for _ in 0..2 {
+            pipeline.render_canvas([8, 4], |canvas| {
+                canvas.layer(&colormap, |layer| {
+                    layer.stipple(&mask, |stipples| stipples.draw(Stipple::default()));
+                });
+                canvas.layer(&colormap, |layer| {
+                    layer.set_opacity(0.5);
+                    layer.set_blend_mode(BlendMode::Multiply);
+                    layer.stipple(&mask, |stipples| stipples.draw(Stipple::default()));
+                });
+            });
+        }
+
+        let frame = vec![
+            Op::RenderFrame([8, 4]),
+            Op::Draw(1, 2, None, None, BlendMode::Normal, 1),
+            Op::RenderFrame([8, 4]),
+            Op::Draw(1, 2, None, None, BlendMode::Normal, 1),
+            Op::Composite([8, 4], 0.5, BlendMode::Multiply),
+            Op::Read([8, 4]),
+        ];
+
+        let mut expected = vec![
+            Op::UploadColormap([4, 4]),
+            Op::UploadMask([8, 8], 1),
+            Op::CreateTarget([8, 4]),
+            Op::CreateTarget([8, 4]),
+        ];
+        expected.extend(frame.iter().cloned());
+        expected.extend(frame);
+
+        assert_eq!(expected, pipeline.backend().ops);
+    }
+
+    #[test]
+    fn merges_stipple_gates_into_batches() {
+        let (mut pipeline, colormap, dot) =
+            testing::pipeline(RecordingBackend::default(), |_x, _y| [0.0; 4]);
+        let dash = pipeline.mask_from_image(image::GrayImage::new(4, 4), 0);
+
+        for order_independent in [false, true].iter() {
+            pipeline.render_canvas([8, 4], |canvas| {
+                canvas.layer(&colormap, |layer| {
+                    layer.set_order_independent(*order_independent);
+                    for mask in [&dot, &dot, &dash, &dot].iter() {
+                        layer.stipple(mask, |stipples| stipples.draw(Stipple::default()));
+                    }
+                    layer.stipple(&dot, |_stipples| {});
+                    layer.stipple(&dot, |stipples| {
+                        stipples.set_blend_mode(BlendMode::Multiply);
+                        stipples.draw(Stipple::default());
+                    });
+                    for _ in 0..2 {
+                        layer.stipple(&dot, |stipples| {
+                            stipples.set_blend_mode(BlendMode::Lighten);
+                            stipples.draw(Stipple::default());
+                        });
+                    }
+                });
+            });
+
+            let draws: Vec<Op> = pipeline
+                .backend_mut()
+                .ops
+                .drain(..)
+                .filter(|op| matches!(op, Op::Draw(..)))
+                .collect();
+            let expected = if *order_independent {
+                vec![
+                    Op::Draw(1, 2, None, None, BlendMode::Normal, 3),
+                    Op::Draw(1, 3, None, None, BlendMode::Normal, 1),
+                    Op::Draw(1, 2, None, None, BlendMode::Multiply, 1),
+                    Op::Draw(1, 2, None, None, BlendMode::Lighten, 1),
+                    Op::Draw(1, 2, None, None, BlendMode::Lighten, 1),
+                ]
+            } else {
+                vec![
+                    Op::Draw(1, 2, None, None, BlendMode::Normal, 2),
+                    Op::Draw(1, 3, None, None, BlendMode::Normal, 1),
+                    Op::Draw(1, 2, None, None, BlendMode::Normal, 1),
+                    Op::Draw(1, 2, None, None, BlendMode::Multiply, 1),
+                    Op::Draw(1, 2, None, None, BlendMode::Lighten, 1),
+                    Op::Draw(1, 2, None, None, BlendMode::Lighten, 1),
+                ]
+            };
+            assert_eq!(expected.len(), pipeline.batch_count());
+            assert_eq!(expected, draws);
+        }
+    }
+
+    #[test]
+    fn streams_stipples_in_chunks() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(RecordingBackend::default(), |_x, _y| [0.0; 4]);
+
+        pipeline.stream_canvas([8, 4], |canvas| {
+            canvas.layer(&colormap, |layer| {
+                layer.stipple(&mask, |stipples| {
+                    for _ in 0..1100 {
+                        stipples.draw(Stipple::default());
+                    }
+                    stipples.set_blend_mode(BlendMode::Multiply);
+                    for _ in 0..10 {
+                        stipples.draw(Stipple::default());
+                    }
+                });
+            });
+        });
+
+        let draws: Vec<&Op> = pipeline
+            .backend()
+            .ops
+            .iter()
+            .filter(|op| matches!(op, Op::Draw(..)))
+            .collect();
+        assert_eq!(
+            vec![
+                &Op::Draw(1, 2, None, None, BlendMode::Normal, 512),
+                &Op::Draw(1, 2, None, None, BlendMode::Normal, 512),
+                &Op::Draw(1, 2, None, None, BlendMode::Normal, 76),
+                &Op::Draw(1, 2, None, None, BlendMode::Multiply, 10),
+            ],
+            draws
+        );
+
+        // chunks with the same blend mode are one batch, like the batches of a canvas
+        assert_eq!(2, pipeline.batch_count());
+    }
+
+    #[test]
+    fn stops_cancelled_streams() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(RecordingBackend::default(), |_x, _y| [0.0; 4]);
+
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        pipeline.set_cancel_token(cancel);
+        pipeline.set_progress_callback(move |_progress| token.cancel());
+
+        let mut generated = 0;
+        let result = pipeline.try_stream_canvas([8, 4], |canvas| {
+            canvas.layer(&colormap, |layer| {
+                layer.stipple(&mask, |stipples| {
+                    while !stipples.is_cancelled() {
+                        stipples.draw(Stipple::default());
+                        generated += 1;
+                    }
+                });
+            });
+        });
+
+        match result {
+            Err(DaliError::Cancelled) => {}
+            _ => panic!("Expected the stream to be cancelled"),
+        }
+        assert_eq!(INSTANCE_CHUNK_SIZE, generated);
+    }
+
+    #[test]
+    fn collects_render_stats() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(RecordingBackend::default(), |_x, _y| [0.0; 4]);
+        let texture = pipeline.texture_from_image(image::GrayImage::new(4, 4), 0);
+
+        pipeline.set_collect_stats(true);
+        pipeline.render_canvas_tiled([8, 4], [4, 4], |canvas| {
+            canvas.layer(&colormap, |layer| {
+                layer.stipple(&mask, |stipples| {
+                    (0..600).for_each(|_| stipples.draw(Stipple::default()))
+                });
+                layer.stipple(&mask, |_stipples| {});
+            });
+            canvas.layer(&colormap, |layer| {
+                layer.set_opacity(0.5);
+                layer.stipple_with_texture(&mask, &texture, |stipples| {
+                    stipples.draw(Stipple::default())
+                });
+            });
+        });
+
+        let stats = pipeline
+            .render_stats()
+            .expect("Should have collected stats");
+        assert_eq!(
+            (2, 3, 601),
+            (stats.layers, stats.stipple_gates, stats.instances)
+        );
+        // in each tile: two chunks, a textured stipple, and the composite of the isolated layer
+        assert_eq!(8, stats.draw_calls);
+        assert_eq!(16, stats.texture_binds);
+        assert_eq!(2, stats.layer_times.len());
+
+        pipeline.set_collect_stats(false);
+        pipeline.render_canvas([4, 4], |_canvas| {});
+        assert!(pipeline.render_stats().is_none());
+    }
+
+    #[test]
+    fn renders_tiles_in_rows() {
+        let mut pipeline = DaliPipeline::new(RecordingBackend::default());
+        let image = pipeline.render_canvas_tiled([10, 5], [4, 4], |_canvas| {});
+
+        let frames: Vec<&Op> = pipeline
+            .backend()
+            .ops
+            .iter()
+            .filter(|op| matches!(op, Op::RenderFrame(_)))
+            .collect();
+        assert_eq!((10, 5), image.dimensions());
+        assert_eq!(
+            vec![
+                &Op::RenderFrame([4, 4]),
+                &Op::RenderFrame([4, 4]),
+                &Op::RenderFrame([2, 4]),
+                &Op::RenderFrame([4, 1]),
+                &Op::RenderFrame([4, 1]),
+                &Op::RenderFrame([2, 1]),
+            ],
+            frames
+        );
+    }
+
+    #[test]
+    fn renders_files_in_one_pass() {
+        let mut pipeline = DaliPipeline::new(RecordingBackend::default());
+        let path = std::env::temp_dir().join(format!("dali-passes-{}.png", std::process::id()));
+        let render = |pipeline: &mut DaliPipeline<RecordingBackend>, size: [u32; 2]| {
+            pipeline.render_canvas_to_file(size, &path, ImageFileFormat::Png, |_canvas| {});
+            std::fs::remove_file(&path).expect("Should have removed file");
+            pipeline.backend_mut().ops.drain(..).collect::<Vec<Op>>()
+        };
+
+        // the canvas is rendered once, and read back in strips from the top
+        let ops = render(&mut pipeline, [16, 600]);
+        let frames = ops.iter().filter(|op| matches!(op, Op::RenderFrame(_)));
+        assert_eq!(1, frames.count());
+        let reads: Vec<&Op> = ops
+            .iter()
+            .filter(|op| matches!(op, Op::ReadRows(..)))
+            .collect();
+        assert_eq!(
+            vec![
+                &Op::ReadRows([16, 600], 344, 256),
+                &Op::ReadRows([16, 600], 88, 256),
+                &Op::ReadRows([16, 600], 0, 88),
+            ],
+            reads
+        );
+
+        // canvases larger than a target are rendered once per tile
+        pipeline.backend_mut().max_target_size = Some(256);
+        let ops = render(&mut pipeline, [600, 300]);
+        let frames = ops.iter().filter(|op| matches!(op, Op::RenderFrame(_)));
+        assert_eq!(6, frames.count());
+    }
+
+    #[test]
+    fn reuses_targets() {
+        let mut pipeline = DaliPipeline::new(RecordingBackend::default());
+        pipeline.render_canvas([4, 4], |_canvas| {});
+        pipeline.render_canvas([4, 4], |_canvas| {});
+        pipeline.render_canvas([2, 2], |_canvas| {});
+
+        let targets: Vec<&Op> = pipeline
+            .backend()
+            .ops
+            .iter()
+            .filter(|op| matches!(op, Op::CreateTarget(_)))
+            .collect();
+        assert_eq!(
+            vec![&Op::CreateTarget([4, 4]), &Op::CreateTarget([2, 2])],
+            targets
+        );
+    }
+
+    #[test]
+    fn reports_backend_errors() {
+        let mut pipeline = DaliPipeline::new(RecordingBackend {
+            fail_targets: true,
+            ..RecordingBackend::default()
+        });
+
+        match pipeline.try_render_canvas([4, 4], |_canvas| {}) {
+            Err(DaliError::Surface(_)) => {}
+            _ => panic!("Expected a surface error"),
+        }
+    }
+}
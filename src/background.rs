@@ -0,0 +1,36 @@
+use crate::backend::{DaliBackend, GlBackend};
+use crate::colormap::ColormapHandle;
+use crate::WindowSurface;
+
+/// The color of blank paper.  Targets hold the canvas color over white paper, and alpha is coverage.
+pub(crate) const PAPER: [f32; 4] = [1.0, 1.0, 1.0, 0.0];
+
+/// The starting canvas, which layers are painted over.  Set it with [CanvasGate::set_background](crate::CanvasGate::set_background)
+pub enum Background<'a, B: DaliBackend = GlBackend<WindowSurface>> {
+    /// A solid RGBA color, with straight alpha, over white paper
+    /// Default: [1.0, 1.0, 1.0, 0.0] (blank paper)
+    Color([f32; 4]),
+    /// A colormap, stretched over the canvas
+    Colormap(&'a ColormapHandle<B>),
+    /// An image, such as a previous render, stretched over the canvas
+    Image(&'a image::RgbaImage),
+}
+
+impl<'a, B: DaliBackend> Background<'a, B> {
+    /// The color the target is cleared to, before any image is painted
+    pub(crate) fn clear_color(&self) -> [f32; 4] {
+        match self {
+            Background::Color(color) => {
+                let mix = |c: f32| 1.0 - color[3] + c * color[3];
+                [mix(color[0]), mix(color[1]), mix(color[2]), color[3]]
+            }
+            _ => PAPER,
+        }
+    }
+}
+
+impl<'a, B: DaliBackend> Default for Background<'a, B> {
+    fn default() -> Self {
+        Background::Color(PAPER)
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+/// Controls how a stipple gate is composited onto the canvas.
+///
+/// The canvas starts as white paper, so multiply and darken tint the paper, and screen and lighten don't affect it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BlendMode {
+    /// Paints over the canvas, with premultiplied alpha.  This is the default.
+    #[default]
+    Normal,
+    /// Multiplies the canvas color, which darkens it.  Useful for shadows and glazes.
+    Multiply,
+    /// Inverse of multiply, which lightens the canvas color.
+    Screen,
+    /// Keeps the lighter of the stipple and canvas colors, per channel.
+    /// Partially transparent stipples mix the canvas toward the lighter color.
+    Lighten,
+    /// Keeps the darker of the stipple and canvas colors, per channel.
+    /// Partially transparent stipples mix the canvas toward the darker color.
+    Darken,
+    /// Erases the canvas under the stipple, toward the paper.
+    /// The coverage is removed, and the color moves toward white, so output that keeps the alpha channel keeps the
+    /// straight color of the remaining canvas.
+    EraseAlpha,
+}
+
+/// How the stipple shaders compute their output color, for a blend mode.
+/// This matches the constants in stipple-output.glsl
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum BlendOutput {
+    /// (alpha * rgb, alpha)
+    Premultiplied = 0,
+    /// (mix(white, rgb, alpha), 1), which blends toward the identity for multiply
+    OverWhite = 1,
+    /// (alpha, alpha, alpha, alpha), which moves the color toward white paper as the coverage is removed
+    Erase = 2,
+    /// (mix(canvas, max(canvas, rgb), alpha), alpha), which reads a copy of the canvas taken before the draw
+    BackdropMax = 3,
+    /// (mix(canvas, min(canvas, rgb), alpha), alpha), which reads a copy of the canvas taken before the draw
+    BackdropMin = 4,
+}
+
+impl BlendOutput {
+    /// Returns true if the output reads a copy of the canvas, which must be taken before each draw
+    pub(crate) fn reads_backdrop(self) -> bool {
+        self == BlendOutput::BackdropMax || self == BlendOutput::BackdropMin
+    }
+}
+
+impl BlendMode {
+    pub(crate) fn output(self) -> BlendOutput {
+        match self {
+            BlendMode::Normal | BlendMode::Screen => BlendOutput::Premultiplied,
+            BlendMode::Multiply => BlendOutput::OverWhite,
+            BlendMode::EraseAlpha => BlendOutput::Erase,
+            BlendMode::Lighten => BlendOutput::BackdropMax,
+            BlendMode::Darken => BlendOutput::BackdropMin,
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use std::path::{Path, PathBuf};
+
+use crate::backend::{DaliBackend, GlBackend};
+use crate::WindowSurface;
+
+/// A handle to a greyscale clip mask, which covers the whole canvas.
+/// White areas of the clip are painted, and black areas are protected.
+pub struct ClipHandle<B: DaliBackend = GlBackend<WindowSurface>> {
+    pub(crate) clip: B::Mask,
+    pub(crate) path: Option<PathBuf>,
+}
+
+impl<B: DaliBackend> ClipHandle<B> {
+    /// The image file the clip was loaded from, which is recorded in scene files
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
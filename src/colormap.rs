@@ -1,6 +1,16 @@
-use luminance::pixel::RGBA32F;
-use luminance::texture::{Dim2, Flat, Texture};
+use std::path::{Path, PathBuf};
 
-pub struct ColormapHandle {
-    pub(crate) texture: Texture<Flat, Dim2, RGBA32F>,
+use crate::backend::{DaliBackend, GlBackend};
+use crate::WindowSurface;
+
+pub struct ColormapHandle<B: DaliBackend = GlBackend<WindowSurface>> {
+    pub(crate) texture: B::Colormap,
+    pub(crate) path: Option<PathBuf>,
+}
+
+impl<B: DaliBackend> ColormapHandle<B> {
+    /// The image file the colormap was loaded from, which is recorded in scene files
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
 }
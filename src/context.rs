@@ -0,0 +1,164 @@
+use crate::backend::GlBackend;
+use crate::error::DaliError;
+use crate::headless::HeadlessSurface;
+use crate::render::pipeline::DaliPipeline;
+use crate::window::WindowSurface;
+
+/// The OpenGL profile requested from the driver
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GlProfile {
+    /// A forward-compatible core profile.  This is the default.
+    Core,
+    /// A compatibility profile, for drivers that don't expose a core profile
+    Compatibility,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ContextOptions {
+    pub(crate) title: String,
+    pub(crate) samples: u32,
+    pub(crate) visible: bool,
+    pub(crate) vsync: bool,
+    pub(crate) gl_version: (u32, u32),
+    pub(crate) gl_profile: GlProfile,
+}
+
+impl ContextOptions {
+    pub(crate) fn num_samples(&self) -> Option<u32> {
+        if self.samples == 0 {
+            None
+        } else {
+            Some(self.samples)
+        }
+    }
+}
+
+impl Default for ContextOptions {
+    fn default() -> ContextOptions {
+        ContextOptions {
+            title: "Dali Preview".to_string(),
+            samples: 8,
+            visible: true,
+            vsync: true,
+            gl_version: (3, 3),
+            gl_profile: GlProfile::Core,
+        }
+    }
+}
+
+/// Configures the window and OpenGL context created by [DaliContext]
+/// Start with `DaliContext::builder()`, and finish with `.build()`
+#[derive(Clone, Debug, Default)]
+pub struct DaliContextBuilder {
+    options: ContextOptions,
+}
+
+impl DaliContextBuilder {
+    pub fn new() -> DaliContextBuilder {
+        Self::default()
+    }
+
+    /// Sets the number of MSAA samples for the preview window
+    /// Use 0 to disable multisampling, on drivers that reject 8x MSAA
+    /// Headless pipelines have no window, so they don't request samples
+    /// Default: 8
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.options.samples = samples;
+        self
+    }
+
+    /// Sets the title of the preview window
+    /// Default: "Dali Preview"
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.options.title = title.to_string();
+        self
+    }
+
+    /// Controls whether the window is shown.  Hidden windows can render_canvas, but can't be previewed.
+    /// Default: true
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.options.visible = visible;
+        self
+    }
+
+    /// Controls whether buffer swaps wait for vertical sync
+    /// Default: true
+    pub fn with_vsync(mut self, vsync: bool) -> Self {
+        self.options.vsync = vsync;
+        self
+    }
+
+    /// Requests an OpenGL version.  Dali's shaders require at least 3.3.
+    /// Default: (3, 3)
+    pub fn with_gl_version(mut self, major: u32, minor: u32) -> Self {
+        self.options.gl_version = (major, minor);
+        self
+    }
+
+    /// Requests an OpenGL profile
+    /// Default: GlProfile::Core
+    pub fn with_gl_profile(mut self, profile: GlProfile) -> Self {
+        self.options.gl_profile = profile;
+        self
+    }
+
+    pub fn build(self) -> DaliContext {
+        DaliContext {
+            options: self.options,
+        }
+    }
+}
+
+/// Creates a window or offscreen context, and initializes the Dali renderer
+/// Use .pipeline() to start rendering, or .headless_pipeline() to render without a window
+#[derive(Default)]
+pub struct DaliContext {
+    options: ContextOptions,
+}
+
+impl DaliContext {
+    /// Creates a new DaliContext, with the default configuration
+    pub fn new() -> DaliContext {
+        Self::default()
+    }
+
+    /// Configures a new DaliContext
+    pub fn builder() -> DaliContextBuilder {
+        DaliContextBuilder::new()
+    }
+
+    /// Creates a new render pipeline
+    pub fn pipeline(&mut self, size: (u32, u32)) -> DaliPipeline<GlBackend<WindowSurface>> {
+        self.try_pipeline(size).expect("GLFW surface creation")
+    }
+
+    /// Creates a new render pipeline
+    /// Returns an error if the window or GL context can't be created
+    pub fn try_pipeline(
+        &mut self,
+        size: (u32, u32),
+    ) -> Result<DaliPipeline<GlBackend<WindowSurface>>, DaliError> {
+        let surface = WindowSurface::with_options(size, &self.options)
+            .map_err(|e| DaliError::Surface(e.to_string()))?;
+
+        Ok(DaliPipeline::new(GlBackend::new(surface)))
+    }
+
+    /// Creates a new render pipeline, without opening a window
+    /// The pipeline supports render_canvas, but not preview_canvas
+    /// The GL version and profile are taken from the builder
+    pub fn headless_pipeline(&mut self) -> DaliPipeline<GlBackend<HeadlessSurface>> {
+        self.try_headless_pipeline().expect("EGL surface creation")
+    }
+
+    /// Creates a new render pipeline, without opening a window
+    /// Returns an error if EGL is unavailable, or the GL context can't be created
+    pub fn try_headless_pipeline(
+        &mut self,
+    ) -> Result<DaliPipeline<GlBackend<HeadlessSurface>>, DaliError> {
+        let surface = HeadlessSurface::with_options(&self.options)
+            .map_err(|e| DaliError::Surface(e.to_string()))?;
+
+        Ok(DaliPipeline::new(GlBackend::new(surface)))
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::OnceLock;
+
+/// How float colors are quantized to 8 bits, in render_canvas and image files.
+///
+/// Rounding can cause visible banding in smooth gradients on large prints.
+/// Dithering replaces the bands with fine noise, which is invisible at print resolution.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Dither {
+    /// Rounds to the nearest value.  This is the default.
+    #[default]
+    None,
+    /// Adds an 8x8 Bayer matrix, which gives a regular cross-hatched pattern
+    Ordered,
+    /// Adds a 64x64 blue noise tile, which gives an even pattern without visible structure
+    BlueNoise,
+}
+
+impl Dither {
+    /// The quantization threshold for a canvas pixel, in [0, 1).  Values are quantized with floor(v * 255 + threshold).
+    pub(crate) fn threshold(self, x: u32, y: u32) -> f32 {
+        match self {
+            Dither::None => 0.5,
+            Dither::Ordered => (bayer(x % 8, y % 8) as f32 + 0.5) / 64.0,
+            Dither::BlueNoise => {
+                let noise = blue_noise();
+                let index = (y % NOISE_SIZE) * NOISE_SIZE + x % NOISE_SIZE;
+                (noise[index as usize] as f32 + 0.5) / (NOISE_SIZE * NOISE_SIZE) as f32
+            }
+        }
+    }
+
+    /// Quantizes a color channel in [0, 1] to 8 bits
+    pub(crate) fn quantize(self, value: f32, x: u32, y: u32) -> u8 {
+        let threshold = self.threshold(x, y);
+        (value.clamp(0.0, 1.0) * 255.0 + threshold)
+            .floor()
+            .min(255.0) as u8
+    }
+}
+
+/// The rank of a pixel in an 8x8 Bayer matrix, from 0 to 63
+fn bayer(x: u32, y: u32) -> u32 {
+    // interleaves the bits of x ^ y and y, in reverse order
+    let xy = x ^ y;
+    ((xy & 1) << 5)
+        | ((y & 1) << 4)
+        | ((xy & 2) << 2)
+        | ((y & 2) << 1)
+        | ((xy & 4) >> 1)
+        | ((y & 4) >> 2)
+}
+
+const NOISE_SIZE: u32 = 64;
+
+/// The rank of each pixel in a tileable blue noise pattern, which is generated on first use
+fn blue_noise() -> &'static [u32] {
+    static NOISE: OnceLock<Vec<u32>> = OnceLock::new();
+    NOISE.get_or_init(void_and_cluster)
+}
+
+/// Ranks the pixels of a tile by repeatedly filling the largest void,
+/// which is the pixel with the least energy from the pixels already ranked.
+/// Energy is a gaussian of the (wrapped) distance, so the pattern tiles seamlessly.
+fn void_and_cluster() -> Vec<u32> {
+    const SIGMA: f32 = 1.9;
+
+    let size = NOISE_SIZE as usize;
+    let wrapped = |d: usize| d.min(size - d) as f32;
+    let mut kernel = vec![0.0f32; size * size];
+    for dy in 0..size {
+        for dx in 0..size {
+            let distance = wrapped(dx).powi(2) + wrapped(dy).powi(2);
+            kernel[dy * size + dx] = (-distance / (2.0 * SIGMA * SIGMA)).exp();
+        }
+    }
+
+    let mut energy = vec![0.0f32; size * size];
+    let mut ranks = vec![u32::MAX; size * size];
+    for rank in 0..(size * size) as u32 {
+        let void = (0..size * size)
+            .filter(|i| ranks[*i] == u32::MAX)
+            .min_by(|a, b| energy[*a].total_cmp(&energy[*b]))
+            .expect("Unranked pixels remain");
+
+        ranks[void] = rank;
+        let (vx, vy) = (void % size, void / size);
+        for y in 0..size {
+            let dy = (y + size - vy) % size;
+            for x in 0..size {
+                let dx = (x + size - vx) % size;
+                energy[y * size + x] += kernel[dy * size + dx];
+            }
+        }
+    }
+
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blue_noise, Dither, NOISE_SIZE};
+
+    #[test]
+    fn ranks_each_threshold_once() {
+        let mut bayer: Vec<u32> = (0..64).map(|i| super::bayer(i % 8, i / 8)).collect();
+        bayer.sort_unstable();
+        assert_eq!((0..64).collect::<Vec<u32>>(), bayer);
+
+        let mut noise = blue_noise().to_vec();
+        noise.sort_unstable();
+        assert_eq!((0..NOISE_SIZE * NOISE_SIZE).collect::<Vec<u32>>(), noise);
+    }
+
+    #[test]
+    fn dithers_to_the_mean() {
+        // a value between two levels is quantized to both, in proportion to its distance from each
+        let value = 100.25 / 255.0;
+        for dither in [Dither::Ordered, Dither::BlueNoise].iter() {
+            let sum: u32 = (0..64)
+                .flat_map(|y| (0..64).map(move |x| (x, y)))
+                .map(|(x, y)| dither.quantize(value, x, y) as u32)
+                .sum();
+            let mean = sum as f32 / 4096.0;
+            assert!(
+                (mean - 100.25).abs() < 0.01,
+                "{:?} mean was {}",
+                dither,
+                mean
+            );
+        }
+
+        assert_eq!(100, Dither::None.quantize(value, 0, 0));
+        assert_eq!(101, Dither::None.quantize(100.5 / 255.0, 0, 0));
+        assert_eq!(255, Dither::Ordered.quantize(1.0, 7, 7));
+    }
+}
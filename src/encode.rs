@@ -0,0 +1,305 @@
+use std::io::{self, Seek, Write};
+use std::path::Path;
+
+use crate::error::DaliError;
+
+/// The file format of a canvas rendered with [render_canvas_to_file](crate::DaliPipeline::render_canvas_to_file)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageFileFormat {
+    /// Lossless RGBA.  Rows are compressed as they are rendered.
+    Png,
+    /// Lossless, uncompressed RGBA.  Rows are written as they are rendered.
+    Tiff,
+    /// Lossy RGB, with a quality from 1 to 100, and at most 65535 pixels on each side.
+    /// The JPEG encoder needs the whole image, so the rows are collected in memory, without the alpha channel.
+    /// Only the rendering overlaps with encoding, and the memory isn't reduced.
+    Jpeg { quality: u8 },
+}
+
+impl ImageFileFormat {
+    /// Guesses the format from the file extension.  JPEGs are encoded with a quality of 90.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFileFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFileFormat::Png),
+            "tif" | "tiff" => Some(ImageFileFormat::Tiff),
+            "jpg" | "jpeg" => Some(ImageFileFormat::Jpeg { quality: 90 }),
+            _ => None,
+        }
+    }
+}
+
+/// Receives strips of rows, from the top of the image.  Strips have the full width of the image.
+pub(crate) type StripSink<'a> = dyn FnMut(&image::RgbaImage) -> Result<(), DaliError> + 'a;
+
+/// Encodes an image, with strips of rows that are passed to the sink by `strips`.
+/// The encoder only holds a strip (or a TIFF strip of about 8KB) at a time, except for JPEG.
+/// Returns an error without finishing the image if the strips end early.
+pub(crate) fn encode<W, S>(
+    mut writer: W,
+    size: [u32; 2],
+    format: ImageFileFormat,
+    strips: S,
+) -> Result<(), DaliError>
+where
+    W: Write + Seek,
+    S: FnOnce(&mut StripSink) -> Result<(), DaliError>,
+{
+    let strips = complete(size, strips);
+    match format {
+        ImageFileFormat::Png => encode_png(&mut writer, size, strips)?,
+        ImageFileFormat::Tiff => encode_tiff(&mut writer, size, strips)?,
+        ImageFileFormat::Jpeg { quality } => encode_jpeg(&mut writer, size, quality, strips)?,
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Passes the strips through, and returns an error if they have fewer rows than the image.
+/// A failed or cancelled render ends the strips early, and the truncated image would look complete.
+fn complete<S>(size: [u32; 2], strips: S) -> impl FnOnce(&mut StripSink) -> Result<(), DaliError>
+where
+    S: FnOnce(&mut StripSink) -> Result<(), DaliError>,
+{
+    move |sink| {
+        let mut rows = 0;
+        strips(&mut |strip| {
+            rows += strip.height();
+            sink(strip)
+        })?;
+
+        if rows < size[1] {
+            return Err(DaliError::Encoding(format!(
+                "the image ended after {} of {} rows",
+                rows, size[1]
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn encode_png<W, S>(writer: W, size: [u32; 2], strips: S) -> Result<(), DaliError>
+where
+    W: Write,
+    S: FnOnce(&mut StripSink) -> Result<(), DaliError>,
+{
+    let mut encoder = png::Encoder::new(writer, size[0], size[1]);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut png = encoder.write_header()?;
+
+    // png's StreamWriter doesn't finish the zlib stream, so the rows are filtered and compressed here
+    let chunks = IdatWriter {
+        png: &mut png,
+        buffer: Vec::new(),
+    };
+    let mut zlib = deflate::write::ZlibEncoder::new(chunks, deflate::Compression::Default);
+    let mut filtered = Vec::new();
+
+    strips(&mut |strip| {
+        for row in strip.chunks(strip.width() as usize * 4) {
+            sub_filter(row, &mut filtered);
+            zlib.write_all(&filtered)?;
+        }
+        Ok(())
+    })?;
+
+    zlib.finish()?.finish()?;
+    Ok(())
+}
+
+/// Filters a row of RGBA pixels with the PNG sub filter, which stores the difference from the pixel on the left
+fn sub_filter(row: &[u8], filtered: &mut Vec<u8>) {
+    const SUB: u8 = 1;
+
+    filtered.clear();
+    filtered.push(SUB);
+    filtered.extend(row.iter().enumerate().map(|(i, byte)| {
+        if i < 4 {
+            *byte
+        } else {
+            byte.wrapping_sub(row[i - 4])
+        }
+    }));
+}
+
+/// Writes compressed image data into IDAT chunks
+struct IdatWriter<'p, W: Write> {
+    png: &'p mut png::Writer<W>,
+    buffer: Vec<u8>,
+}
+
+impl<'p, W: Write> IdatWriter<'p, W> {
+    const CHUNK_SIZE: usize = 1 << 16;
+
+    fn finish(self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.png.write_chunk(png::chunk::IDAT, &self.buffer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'p, W: Write> Write for IdatWriter<'p, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= Self::CHUNK_SIZE {
+            self.png.write_chunk(png::chunk::IDAT, &self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn encode_tiff<W, S>(writer: W, size: [u32; 2], strips: S) -> Result<(), DaliError>
+where
+    W: Write + Seek,
+    S: FnOnce(&mut StripSink) -> Result<(), DaliError>,
+{
+    let mut tiff = tiff::encoder::TiffEncoder::new(writer)?;
+    let mut image = tiff.new_image::<tiff::encoder::colortype::RGBA8>(size[0], size[1])?;
+
+    // TIFF strips have a fixed number of rows, which usually differs from the strips we receive
+    let mut pending: Vec<u8> = Vec::new();
+    strips(&mut |strip| {
+        pending.extend_from_slice(strip);
+
+        let mut written = 0;
+        loop {
+            let samples = image.next_strip_sample_count() as usize;
+            if samples == 0 || pending.len() - written < samples {
+                break;
+            }
+
+            image.write_strip(&pending[written..written + samples])?;
+            written += samples;
+        }
+
+        pending.drain(..written);
+        Ok(())
+    })?;
+
+    image.finish()?;
+    Ok(())
+}
+
+fn encode_jpeg<W, S>(mut writer: W, size: [u32; 2], quality: u8, strips: S) -> Result<(), DaliError>
+where
+    W: Write,
+    S: FnOnce(&mut StripSink) -> Result<(), DaliError>,
+{
+    // the encoder stores the dimensions in 16 bits
+    if size[0] > u16::MAX as u32 || size[1] > u16::MAX as u32 {
+        return Err(DaliError::Encoding(format!(
+            "JPEG images can't be larger than 65535x65535, and the image is {}x{}",
+            size[0], size[1]
+        )));
+    }
+
+    let mut rgb = Vec::with_capacity(size[0] as usize * size[1] as usize * 3);
+    strips(&mut |strip| {
+        for pixel in strip.chunks(4) {
+            rgb.extend_from_slice(&pixel[..3]);
+        }
+        Ok(())
+    })?;
+
+    let mut encoder = image::jpeg::JPEGEncoder::new_with_quality(&mut writer, quality);
+    encoder.encode(&rgb, size[0], size[1], image::ColorType::RGB(8))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{encode, ImageFileFormat};
+
+    fn strips(image: &image::RgbaImage, rows: u32) -> Vec<image::RgbaImage> {
+        (0..image.height())
+            .step_by(rows as usize)
+            .map(|y| {
+                let height = rows.min(image.height() - y);
+                image::imageops::crop(&mut image.clone(), 0, y, image.width(), height).to_image()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encodes_strips_losslessly() {
+        let image = image::RgbaImage::from_fn(1200, 37, |x, y| {
+            image::Rgba([(x * 7) as u8, (y * 5) as u8, (x ^ y) as u8, 255])
+        });
+
+        for format in [ImageFileFormat::Png, ImageFileFormat::Tiff].iter() {
+            let mut file = Cursor::new(Vec::new());
+            encode(&mut file, [1200, 37], *format, |sink| {
+                strips(&image, 8).iter().try_for_each(&mut *sink)
+            })
+            .expect("Should have encoded image");
+
+            let decoded = image::load_from_memory(file.get_ref())
+                .expect("Should have decoded image")
+                .to_rgba();
+            assert_eq!(image.to_vec(), decoded.to_vec(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn encodes_jpeg() {
+        let image = image::RgbaImage::from_pixel(40, 30, image::Rgba([200, 40, 40, 255]));
+        let mut file = Cursor::new(Vec::new());
+        encode(
+            &mut file,
+            [40, 30],
+            ImageFileFormat::Jpeg { quality: 95 },
+            |sink| strips(&image, 16).iter().try_for_each(&mut *sink),
+        )
+        .expect("Should have encoded image");
+
+        let decoded = image::load_from_memory(file.get_ref())
+            .expect("Should have decoded image")
+            .to_rgb();
+        assert_eq!((40, 30), decoded.dimensions());
+        assert!(decoded.pixels().all(|p| (p.0[0] as i32 - 200).abs() < 8));
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let image = image::RgbaImage::new(40, 30);
+        for format in [ImageFileFormat::Png, ImageFileFormat::Jpeg { quality: 90 }].iter() {
+            let mut file = Cursor::new(Vec::new());
+            let result = encode(&mut file, [40, 30], *format, |sink| {
+                strips(&image, 16).iter().take(1).try_for_each(&mut *sink)
+            });
+            assert!(result.is_err(), "{:?}", format);
+        }
+
+        let mut file = Cursor::new(Vec::new());
+        let result = encode(
+            &mut file,
+            [70_000, 1],
+            ImageFileFormat::Jpeg { quality: 90 },
+            |_sink| Ok(()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn guesses_format_from_path() {
+        assert_eq!(
+            Some(ImageFileFormat::Png),
+            ImageFileFormat::from_path("print.PNG")
+        );
+        assert_eq!(
+            Some(ImageFileFormat::Tiff),
+            ImageFileFormat::from_path("print.tif")
+        );
+        assert_eq!(None, ImageFileFormat::from_path("print"));
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+
+use luminance::framebuffer::FramebufferError;
+use luminance::shader::program::ProgramError;
+use luminance::tess::{TessError, TessMapError};
+use luminance::texture::TextureError;
+
+/// Errors that can occur while creating a pipeline, uploading assets, or rendering a canvas
+#[derive(Debug)]
+pub enum DaliError {
+    /// The window or offscreen context could not be created, or used
+    Surface(String),
+    /// A texture could not be allocated, or its texels could not be uploaded
+    Texture(TextureError),
+    /// A framebuffer could not be allocated (for example, if it exceeds the GPU's limits)
+    Framebuffer(FramebufferError),
+    /// Stipple geometry could not be created
+    Tess(TessError),
+    /// Stipple instances could not be written to the GPU
+    TessMap(TessMapError),
+    /// A shader program failed to compile or link
+    Program(ProgramError),
+    /// An image file could not be read or decoded
+    Image(image::ImageError),
+    /// A file could not be read or written
+    Io(std::io::Error),
+    /// A scene could not be recorded, or a scene file could not be parsed or replayed
+    SceneFile(String),
+    /// A rendered image could not be encoded
+    Encoding(String),
+    /// The render was cancelled with a [CancelToken](crate::CancelToken)
+    Cancelled,
+}
+
+impl fmt::Display for DaliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DaliError::Surface(e) => write!(f, "surface error: {}", e),
+            DaliError::Texture(e) => write!(f, "texture error: {}", e),
+            DaliError::Framebuffer(e) => write!(f, "framebuffer error: {}", e),
+            DaliError::Tess(e) => write!(f, "tessellation error: {:?}", e),
+            DaliError::TessMap(e) => write!(f, "tessellation error: {}", e),
+            DaliError::Program(e) => write!(f, "shader program error: {}", e),
+            DaliError::Image(e) => write!(f, "image error: {}", e),
+            DaliError::Io(e) => write!(f, "io error: {}", e),
+            DaliError::SceneFile(e) => write!(f, "scene file error: {}", e),
+            DaliError::Encoding(e) => write!(f, "encoding error: {}", e),
+            DaliError::Cancelled => write!(f, "the render was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for DaliError {}
+
+impl From<TextureError> for DaliError {
+    fn from(e: TextureError) -> Self {
+        DaliError::Texture(e)
+    }
+}
+
+impl From<FramebufferError> for DaliError {
+    fn from(e: FramebufferError) -> Self {
+        DaliError::Framebuffer(e)
+    }
+}
+
+impl From<TessError> for DaliError {
+    fn from(e: TessError) -> Self {
+        DaliError::Tess(e)
+    }
+}
+
+impl From<TessMapError> for DaliError {
+    fn from(e: TessMapError) -> Self {
+        DaliError::TessMap(e)
+    }
+}
+
+impl From<ProgramError> for DaliError {
+    fn from(e: ProgramError) -> Self {
+        DaliError::Program(e)
+    }
+}
+
+impl From<image::ImageError> for DaliError {
+    fn from(e: image::ImageError) -> Self {
+        DaliError::Image(e)
+    }
+}
+
+impl From<std::io::Error> for DaliError {
+    fn from(e: std::io::Error) -> Self {
+        DaliError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for DaliError {
+    fn from(e: png::EncodingError) -> Self {
+        DaliError::Encoding(e.to_string())
+    }
+}
+
+impl From<tiff::TiffError> for DaliError {
+    fn from(e: tiff::TiffError) -> Self {
+        DaliError::Encoding(e.to_string())
+    }
+}
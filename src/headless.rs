@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::fmt;
+use std::rc::Rc;
+
+use khronos_egl as egl;
+use luminance::context::GraphicsContext;
+use luminance::state::{GraphicsState, StateQueryError};
+
+use crate::context::{ContextOptions, GlProfile};
+
+/// EGL_PLATFORM_SURFACELESS_MESA, from the EGL_MESA_platform_surfaceless extension
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// Error that can be raised while creating a headless surface.
+#[derive(Debug)]
+pub enum HeadlessSurfaceError {
+    /// libEGL could not be loaded, or does not provide EGL 1.4
+    LoadError(String),
+    /// No EGL display is available, even without a window system
+    NoDisplay,
+    /// No EGL config supports an offscreen OpenGL context
+    NoConfig,
+    /// An EGL call failed
+    EglError(egl::Error),
+    /// The graphics state is not available.
+    /// This happens if a surface has already been created on the current thread.
+    GraphicsStateError(StateQueryError),
+}
+
+impl fmt::Display for HeadlessSurfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeadlessSurfaceError::LoadError(e) => write!(f, "failed to load libEGL: {}", e),
+            HeadlessSurfaceError::NoDisplay => f.write_str("no EGL display"),
+            HeadlessSurfaceError::NoConfig => f.write_str("no EGL config supports OpenGL"),
+            HeadlessSurfaceError::EglError(e) => write!(f, "EGL error: {}", e),
+            HeadlessSurfaceError::GraphicsStateError(e) => {
+                write!(f, "failed to get graphics state: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeadlessSurfaceError {}
+
+impl From<egl::Error> for HeadlessSurfaceError {
+    fn from(e: egl::Error) -> Self {
+        HeadlessSurfaceError::EglError(e)
+    }
+}
+
+/// An offscreen OpenGL context, which renders without opening a window.
+///
+/// The context is created with EGL, and prefers the Mesa surfaceless platform,
+/// so it works on machines without a display server (including Mesa's llvmpipe software rasterizer).
+/// Previews are not available, but [DaliPipeline::render_canvas](crate::DaliPipeline::render_canvas) works normally.
+pub struct HeadlessSurface {
+    egl: egl::DynamicInstance<egl::EGL1_4>,
+    display: egl::Display,
+    context: egl::Context,
+    surface: Option<egl::Surface>,
+    gfx_state: Rc<RefCell<GraphicsState>>,
+}
+
+unsafe impl GraphicsContext for HeadlessSurface {
+    fn state(&self) -> &Rc<RefCell<GraphicsState>> {
+        &self.gfx_state
+    }
+}
+
+impl HeadlessSurface {
+    /// Creates an offscreen context with the default [DaliContextBuilder](crate::DaliContextBuilder) options,
+    /// and makes it current on this thread.
+    /// The default requests an OpenGL 3.3 core profile.  Use `DaliContext::builder()` for other versions.
+    pub fn new() -> Result<HeadlessSurface, HeadlessSurfaceError> {
+        Self::with_options(&ContextOptions::default())
+    }
+
+    /// Creates an offscreen context, and makes it current on this thread.
+    ///
+    /// Only the GL options apply to a headless context:
+    /// - `gl_version` is the requested (major, minor) OpenGL version.  Drivers may return a newer compatible version.
+    /// - `gl_profile` selects the core or compatibility profile.
+    ///
+    /// The title, visibility, vsync and sample count configure the preview window, so they are ignored.
+    /// Nothing is presented, and renders go to single-sampled framebuffer objects.
+    pub(crate) fn with_options(
+        options: &ContextOptions,
+    ) -> Result<HeadlessSurface, HeadlessSurfaceError> {
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_4>::load_required() }
+            .map_err(|e| HeadlessSurfaceError::LoadError(e.to_string()))?;
+
+        let display = Self::display(&egl).ok_or(HeadlessSurfaceError::NoDisplay)?;
+        egl.initialize(display)?;
+        egl.bind_api(egl::OPENGL_API)?;
+
+        // the 1x1 pbuffer is never drawn, so a multisampled config
+        // would only narrow the configs that drivers (like llvmpipe) can offer
+        let config_attributes = [
+            egl::SURFACE_TYPE,
+            egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE,
+            egl::OPENGL_BIT,
+            egl::NONE,
+        ];
+        let config = egl
+            .choose_first_config(display, &config_attributes)?
+            .ok_or(HeadlessSurfaceError::NoConfig)?;
+
+        let (major, minor) = options.gl_version;
+        let profile = match options.gl_profile {
+            GlProfile::Core => egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            GlProfile::Compatibility => egl::CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+        };
+        let context_attributes = [
+            egl::CONTEXT_MAJOR_VERSION,
+            major as egl::Int,
+            egl::CONTEXT_MINOR_VERSION,
+            minor as egl::Int,
+            egl::CONTEXT_OPENGL_PROFILE_MASK,
+            profile,
+            egl::NONE,
+        ];
+        let context = egl.create_context(display, config, None, &context_attributes)?;
+
+        // all rendering goes to framebuffer objects, so we only need a surface
+        // if the driver can't make a context current without one
+        let surfaceless = egl
+            .query_string(Some(display), egl::EXTENSIONS)
+            .map(|e| e.to_string_lossy().contains("EGL_KHR_surfaceless_context"))
+            .unwrap_or(false);
+
+        let surface = if surfaceless {
+            None
+        } else {
+            let pbuffer_attributes = [egl::WIDTH, 1, egl::HEIGHT, 1, egl::NONE];
+            Some(egl.create_pbuffer_surface(display, config, &pbuffer_attributes)?)
+        };
+
+        egl.make_current(display, surface, surface, Some(context))?;
+
+        gl::load_with(|s| match egl.get_proc_address(s) {
+            Some(f) => f as *const c_void,
+            None => std::ptr::null(),
+        });
+
+        let gfx_state = GraphicsState::new().map_err(HeadlessSurfaceError::GraphicsStateError)?;
+
+        Ok(HeadlessSurface {
+            egl,
+            display,
+            context,
+            surface,
+            gfx_state: Rc::new(RefCell::new(gfx_state)),
+        })
+    }
+
+    fn display(egl: &egl::DynamicInstance<egl::EGL1_4>) -> Option<egl::Display> {
+        // the surfaceless platform doesn't need X11 or Wayland, so it works on render farms and CI
+        let surfaceless = egl.upcast::<egl::EGL1_5>().and_then(|egl| unsafe {
+            egl.get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+            .ok()
+        });
+
+        surfaceless.or_else(|| unsafe { egl.get_display(egl::DEFAULT_DISPLAY) })
+    }
+}
+
+impl Drop for HeadlessSurface {
+    fn drop(&mut self) {
+        let _ = self.egl.make_current(self.display, None, None, None);
+        if let Some(surface) = self.surface {
+            let _ = self.egl.destroy_surface(self.display, surface);
+        }
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.terminate(self.display);
+    }
+}
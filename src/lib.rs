@@ -1,57 +1,92 @@
 //! Dali Renderer, is a GPU rendering library that creates high quality digital paintings.
-//! 
-//! Dali is designed to generate output for large canvas prints, which means high DPI, 
-//! high resolution output. Currently, high resolution images (8000x8000) render in ~20 seconds, 
+//!
+//! Dali is designed to generate output for large canvas prints, which means high DPI,
+//! high resolution output. Currently, high resolution images (8000x8000) render in ~20 seconds,
 //! plus 40-60 seconds for JPEG encoding.
-//! 
+//!
 //! Get started with `DaliContext::new().pipeline((800, 600))`, or see
 //! the [examples](https://github.com/austinjones/dali-rs/tree/master/examples).
+//!
+//! On machines without a display, use `DaliContext::new().headless_pipeline()`,
+//! which renders with an offscreen EGL context.
+//! Without any GPU, `DaliPipeline::new(SoftwareRenderer::new())` renders the same canvases on the CPU.
 
-use luminance_glfw::Surface;
-use luminance_glfw::{GlfwSurface, WindowDim, WindowOpt};
-
+pub use backend::{BackendFrame, DaliBackend, FrameCallback, GlBackend, GlTarget, Tile};
+pub use background::Background;
+pub use blend::BlendMode;
+pub use clip::ClipHandle;
 pub use colormap::ColormapHandle;
-pub use mask::MaskHandle;
+pub use context::{DaliContext, DaliContextBuilder, GlProfile};
+pub use dither::Dither;
+pub use encode::ImageFileFormat;
+pub use error::DaliError;
+pub use headless::{HeadlessSurface, HeadlessSurfaceError};
+pub use mask::{MaskFit, MaskHandle};
 pub use render::gate_canvas::CanvasGate;
 pub use render::gate_layer::LayerGate;
 pub use render::gate_stipple::StippleGate;
 pub use render::pipeline::DaliPipeline;
 pub use render::pipeline::PreviewAction;
+pub use render::progress::{CancelToken, ProgressCallback, RenderProgress};
+pub use render::scene::Scene;
+pub use render::stats::RenderStats;
+pub use render::stream::{CanvasStream, LayerStream, StippleStream};
+pub use scene_file::{
+    AssetKind, AssetRecord, BackgroundRecord, LayerRecord, SceneAssets, SceneFile, SceneFormat,
+    StippleBatchRecord, SCENE_FILE_VERSION,
+};
+pub use software::{SoftwareCanvas, SoftwareRenderer, SoftwareTexels};
 pub use stipple::Stipple;
 pub use texture::renderers as texture_renderers;
 pub use texture::TextureHandle;
 pub use texture::TextureRenderer;
+pub use window::{PreviewSurface, WindowSurface};
 
+mod backend;
+mod background;
+mod blend;
+mod clip;
 mod colormap;
+mod context;
+mod dither;
+mod encode;
+mod error;
+mod headless;
 mod mask;
 mod render;
+mod scene_file;
+mod software;
 mod stipple;
+#[cfg(test)]
+mod testing;
 mod texture;
+mod window;
 
-/// A [DaliPipeline] with a GlfwSurface backend
-pub type DaliPipelineGlfw = DaliPipeline<GlfwSurface>;
+/// A [DaliPipeline] with a GLFW window configured by [DaliContextBuilder], from [DaliContext::pipeline]
+pub type DaliPipelineWindow = DaliPipeline<GlBackend<WindowSurface>>;
 
-/// Wraps a GlfwSurface, and initializes the Dali renderer
-/// Use .pipeline() to start rendering
-pub struct DaliContext {}
+/// The original name of [DaliPipelineWindow], which is kept so existing code still compiles
+pub type DaliPipelineGlfw = DaliPipelineWindow;
 
-impl DaliContext {
-    /// Creates a new DaliContext
-    pub fn new() -> DaliContext {
-        DaliContext {}
-    }
+/// A [DaliPipeline] with an offscreen EGL backend
+pub type DaliPipelineHeadless = DaliPipeline<GlBackend<HeadlessSurface>>;
 
-    /// Creates a new render pipeline
-    pub fn pipeline(&mut self, (width, height): (u32, u32)) -> DaliPipeline<GlfwSurface> {
-        let opts = WindowOpt::default().set_num_samples(8);
-        let surface = GlfwSurface::new(WindowDim::Windowed(width, height), "Dali Preview", opts)
-            .expect("GLFW surface creation");
-
-        DaliPipeline::new(surface)
-    }
-}
+/// A [DaliPipeline] which renders on the CPU
+pub type DaliPipelineSoftware = DaliPipeline<SoftwareRenderer>;
 
 #[cfg(test)]
 mod tests {
     // GlfwSurface must be constructed from the main thread, so DaliContext tests are in gltests/main.rs
+    use super::{DaliContext, DaliPipelineGlfw, PreviewAction};
+
+    // these are never called, but they must keep compiling with the signatures from before DaliContextBuilder
+    #[allow(dead_code)]
+    fn glfw_pipeline(context: &mut DaliContext) -> DaliPipelineGlfw {
+        context.pipeline((800, 600))
+    }
+
+    #[allow(dead_code)]
+    fn glfw_preview(pipeline: &mut DaliPipelineGlfw) -> PreviewAction {
+        pipeline.preview_canvas(|_canvas| {})
+    }
 }
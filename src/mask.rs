@@ -1,7 +1,92 @@
-use luminance::pixel::R32F;
-use luminance::texture::{Dim2, Flat, Texture};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{DaliBackend, GlBackend};
+use crate::WindowSurface;
 
 /// A handle to a Dali Mask loaded into GPU memory
-pub struct MaskHandle {
-    pub mask: Texture<Flat, Dim2, R32F>,
+pub struct MaskHandle<B: DaliBackend = GlBackend<WindowSurface>> {
+    pub mask: B::Mask,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) mipmaps: usize,
+    pub(crate) fit: MaskFit,
+}
+
+impl<B: DaliBackend> MaskHandle<B> {
+    /// The image file the mask was loaded from, which is recorded in scene files
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+/// How a non-square mask or texture image is fit into the square stipple.
+/// Set it with [DaliPipeline::set_mask_fit](crate::DaliPipeline::set_mask_fit)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MaskFit {
+    /// Pads the shorter side, so the whole image keeps its aspect ratio.  This is the default.
+    /// Masks are padded with black (no paint), and textures with their mean value, so the padding doesn't shift the tone.
+    #[default]
+    Pad,
+    /// Crops the center of the longer side
+    Crop,
+    /// Stretches the shorter side to fill the stipple
+    Stretch,
+}
+
+impl MaskFit {
+    /// Fits the image into a square, and fills padding with the given value
+    pub(crate) fn apply(self, image: image::GrayImage, pad: u8) -> image::GrayImage {
+        let (w, h) = image.dimensions();
+        if w == h {
+            return image;
+        }
+
+        let long = w.max(h);
+        let short = w.min(h);
+        match self {
+            MaskFit::Pad => {
+                let mut square = image::GrayImage::from_pixel(long, long, image::Luma([pad]));
+                image::imageops::replace(&mut square, &image, (long - w) / 2, (long - h) / 2);
+                square
+            }
+            MaskFit::Crop => {
+                let mut image = image;
+                let x = (w - short) / 2;
+                let y = (h - short) / 2;
+                image::imageops::crop(&mut image, x, y, short, short).to_image()
+            }
+            MaskFit::Stretch => {
+                image::imageops::resize(&image, long, long, image::FilterType::Triangle)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MaskFit;
+
+    #[test]
+    fn fits_images_into_squares() {
+        // a 4x2 image, with a bright right half
+        let image =
+            image::GrayImage::from_fn(4, 2, |x, _y| image::Luma([if x < 2 { 0 } else { 200 }]));
+
+        let padded = MaskFit::Pad.apply(image.clone(), 7);
+        assert_eq!((4, 4), padded.dimensions());
+        assert_eq!(7, padded.get_pixel(3, 0).0[0]);
+        assert_eq!(200, padded.get_pixel(3, 1).0[0]);
+        assert_eq!(7, padded.get_pixel(3, 3).0[0]);
+
+        let cropped = MaskFit::Crop.apply(image.clone(), 7);
+        assert_eq!((2, 2), cropped.dimensions());
+        assert_eq!(0, cropped.get_pixel(0, 0).0[0]);
+        assert_eq!(200, cropped.get_pixel(1, 1).0[0]);
+
+        let stretched = MaskFit::Stretch.apply(image, 7);
+        assert_eq!((4, 4), stretched.dimensions());
+        assert_eq!(0, stretched.get_pixel(0, 3).0[0]);
+        assert!(stretched.get_pixel(3, 0).0[0] > 190);
+    }
 }
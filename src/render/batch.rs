@@ -0,0 +1,76 @@
+use crate::backend::DaliBackend;
+use crate::render::gate_layer::LayerGate;
+use crate::render::gate_stipple::StippleGate;
+use crate::stipple::Stipple;
+
+/// Stipple gates that share a mask, texture and blend mode, which are drawn with one instanced draw call
+pub(crate) struct Batch<'g, 'a, B: DaliBackend> {
+    gates: Vec<&'g StippleGate<'a, B>>,
+}
+
+impl<'g, 'a, B: DaliBackend> Batch<'g, 'a, B> {
+    /// The gate which provides the mask, texture and blend mode of the batch
+    pub(crate) fn gate(&self) -> &'g StippleGate<'a, B> {
+        self.gates[0]
+    }
+
+    /// The instances of every gate in the batch, in order.
+    /// Batches with several gates are copied into the scratch buffer.
+    pub(crate) fn instances<'s>(&'s self, scratch: &'s mut Vec<Stipple>) -> &'s [Stipple] {
+        if let [gate] = self.gates.as_slice() {
+            return gate.instances();
+        }
+
+        scratch.clear();
+        for gate in self.gates.iter() {
+            scratch.extend_from_slice(gate.instances());
+        }
+        scratch.as_slice()
+    }
+
+    fn accepts(&self, gate: &StippleGate<'a, B>) -> bool {
+        let first = self.gate();
+        let same_texture = match (first.texture, gate.texture) {
+            (Some(a), Some(b)) => std::ptr::eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+
+        // blend modes which read the canvas copy it before each draw, so each gate sees the gates before it
+        let reads_backdrop = gate.blend_mode.output().reads_backdrop();
+
+        std::ptr::eq(first.mask, gate.mask)
+            && same_texture
+            && first.blend_mode == gate.blend_mode
+            && !reads_backdrop
+    }
+}
+
+/// Merges the stipple gates of a layer into batches.
+/// Consecutive gates are merged, so the draw order is unchanged.
+/// Gates with a blend mode which reads the canvas, like lighten, are never merged.
+/// If the layer is order independent, every gate joins the first batch it matches.
+pub(crate) fn batches<'g, 'a, B: DaliBackend>(
+    layer: &'g LayerGate<'a, B>,
+) -> Vec<Batch<'g, 'a, B>> {
+    let mut batches: Vec<Batch<B>> = Vec::new();
+
+    for gate in layer.stipples() {
+        if gate.instances().is_empty() {
+            continue;
+        }
+
+        let batch = if layer.order_independent {
+            batches.iter_mut().find(|batch| batch.accepts(gate))
+        } else {
+            batches.last_mut().filter(|batch| batch.accepts(gate))
+        };
+
+        match batch {
+            Some(batch) => batch.gates.push(gate),
+            None => batches.push(Batch { gates: vec![gate] }),
+        }
+    }
+
+    batches
+}
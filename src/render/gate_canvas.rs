@@ -1,31 +1,81 @@
 use std::iter::Iterator;
 
+use crate::backend::{DaliBackend, GlBackend};
+use crate::background::Background;
+use crate::clip::ClipHandle;
 use crate::colormap::ColormapHandle;
 use crate::render::gate_layer::LayerGate;
+use crate::WindowSurface;
 
 /// CanvasGate manages the Framebuffer render, binding ColorMaps, and layers via [layer]
 /// Manages high-level resources such as Color Maps, Textures, and Layers.
-pub struct CanvasGate<'a> {
-    layers: Vec<LayerGate<'a>>
+pub struct CanvasGate<'a, B: DaliBackend = GlBackend<WindowSurface>> {
+    background: Background<'a, B>,
+    layers: Vec<LayerGate<'a, B>>,
 }
 
-impl<'a> CanvasGate<'a> {
-    pub(crate) fn new() -> CanvasGate<'a> {
+impl<'a, B: DaliBackend> CanvasGate<'a, B> {
+    pub(crate) fn new() -> CanvasGate<'a, B> {
         CanvasGate {
-            layers: Vec::new()
+            background: Background::default(),
+            layers: Vec::new(),
         }
     }
 
-    pub fn layer<F>(&mut self, colormap: &'a ColormapHandle, callback: F)
-        where
-            F: FnOnce(&mut LayerGate<'a>),
+    /// Sets the starting canvas, which is painted before any layers.
+    /// Default: Background::Color([1.0, 1.0, 1.0, 0.0]) (blank paper)
+    pub fn set_background(&mut self, background: Background<'a, B>) {
+        self.background = background;
+    }
+
+    pub fn layer<F>(&mut self, colormap: &'a ColormapHandle<B>, callback: F)
+    where
+        F: FnOnce(&mut LayerGate<'a, B>),
     {
         let mut layer = LayerGate::new(colormap);
         callback(&mut layer);
         self.layers.push(layer);
     }
 
-    pub(crate) fn layers(&self) -> impl Iterator<Item=&LayerGate<'a>> {
+    /// Adds a layer which only paints where the clip mask is white.
+    /// The clip is stretched over the whole canvas.
+    pub fn layer_with_clip<F>(
+        &mut self,
+        colormap: &'a ColormapHandle<B>,
+        clip: &'a ClipHandle<B>,
+        callback: F,
+    ) where
+        F: FnOnce(&mut LayerGate<'a, B>),
+    {
+        let mut layer = LayerGate::new_with_clip(colormap, clip);
+        callback(&mut layer);
+        self.layers.push(layer);
+    }
+
+    pub(crate) fn push_layer(&mut self, layer: LayerGate<'a, B>) {
+        self.layers.push(layer);
+    }
+
+    /// The starting canvas
+    pub fn background(&self) -> &Background<'a, B> {
+        &self.background
+    }
+
+    /// The layers, in the order they are painted
+    pub fn layers(&self) -> impl Iterator<Item = &LayerGate<'a, B>> {
         self.layers.iter()
     }
+
+    /// The layers, in the order they are painted
+    pub fn layers_mut(&mut self) -> impl Iterator<Item = &mut LayerGate<'a, B>> {
+        self.layers.iter_mut()
+    }
+
+    /// Removes the layers which don't match the predicate
+    pub fn retain_layers<F>(&mut self, predicate: F)
+    where
+        F: FnMut(&LayerGate<'a, B>) -> bool,
+    {
+        self.layers.retain(predicate);
+    }
 }
@@ -1,27 +1,90 @@
+use crate::backend::{DaliBackend, GlBackend};
+use crate::blend::BlendMode;
+use crate::clip::ClipHandle;
 use crate::colormap::ColormapHandle;
 use crate::render::gate_stipple::StippleGate;
 use crate::texture::TextureHandle;
-use crate::MaskHandle;
+use crate::{MaskHandle, WindowSurface};
 
 /// Handles the bulk of the rendering and GLSL interaction
 /// CanvasGate binds a framebuffer, and then initializes the LayerGate
 /// LayerGate renders primitives such as Stipple instances.
-pub struct LayerGate<'a> {
-    pub(crate) colormap: &'a ColormapHandle,
-    stipples: Vec<StippleGate<'a>>,
+pub struct LayerGate<'a, B: DaliBackend = GlBackend<WindowSurface>> {
+    pub(crate) colormap: &'a ColormapHandle<B>,
+    pub(crate) clip: Option<&'a ClipHandle<B>>,
+    pub(crate) opacity: f32,
+    pub(crate) blend_mode: BlendMode,
+    pub(crate) isolated: bool,
+    pub(crate) order_independent: bool,
+    stipples: Vec<StippleGate<'a, B>>,
 }
 
-impl<'a> LayerGate<'a> {
-    pub fn new(colormap: &'a ColormapHandle) -> LayerGate<'a> {
+impl<'a, B: DaliBackend> LayerGate<'a, B> {
+    pub fn new(colormap: &'a ColormapHandle<B>) -> LayerGate<'a, B> {
         LayerGate {
             colormap,
+            clip: None,
+            opacity: 1.0,
+            blend_mode: BlendMode::default(),
+            isolated: false,
+            order_independent: false,
             stipples: Vec::new(),
         }
     }
 
-    pub fn stipple<F>(&mut self, mask: &'a MaskHandle, callback: F)
+    pub fn new_with_clip(
+        colormap: &'a ColormapHandle<B>,
+        clip: &'a ClipHandle<B>,
+    ) -> LayerGate<'a, B> {
+        LayerGate {
+            clip: Some(clip),
+            ..LayerGate::new(colormap)
+        }
+    }
+
+    /// Sets the opacity of the whole layer, which is applied when the layer is composited onto the canvas.
+    /// Layers with an opacity below 1 are rendered in isolation.
+    /// Range: [0, 1]
+    /// Default: 1.0
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity;
+    }
+
+    /// Sets the blend mode used to composite the whole layer onto the canvas.
+    /// Layers with a blend mode other than Normal are rendered in isolation.
+    /// Default: BlendMode::Normal
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    /// Renders the layer into its own buffer, starting from blank paper, and then composites it onto the canvas.
+    /// Stipple blend modes within an isolated layer only affect the layer.
+    /// Default: false
+    pub fn set_isolated(&mut self, isolated: bool) {
+        self.isolated = isolated;
+    }
+
+    /// Allows stipple gates which share a mask, texture and blend mode to be drawn in one batch,
+    /// even when other gates are drawn between them.
+    /// Only use this when the order of the stipples doesn't affect the result, e.g. when they don't overlap.
+    /// Default: false
+    pub fn set_order_independent(&mut self, order_independent: bool) {
+        self.order_independent = order_independent;
+    }
+
+    /// True if stipple gates can be drawn out of order
+    pub fn order_independent(&self) -> bool {
+        self.order_independent
+    }
+
+    /// True if the layer needs its own buffer
+    pub(crate) fn is_isolated(&self) -> bool {
+        self.isolated || self.opacity < 1.0 || self.blend_mode != BlendMode::Normal
+    }
+
+    pub fn stipple<F>(&mut self, mask: &'a MaskHandle<B>, callback: F)
     where
-        F: FnOnce(&mut StippleGate),
+        F: FnOnce(&mut StippleGate<B>),
     {
         let mut stipple = StippleGate::new(mask);
         callback(&mut stipple);
@@ -30,18 +93,38 @@ impl<'a> LayerGate<'a> {
 
     pub fn stipple_with_texture<F>(
         &mut self,
-        mask: &'a MaskHandle,
-        texture: &'a TextureHandle,
+        mask: &'a MaskHandle<B>,
+        texture: &'a TextureHandle<B>,
         callback: F,
     ) where
-        F: FnOnce(&mut StippleGate),
+        F: FnOnce(&mut StippleGate<B>),
     {
         let mut stipple = StippleGate::new_with_texture(mask, texture);
         callback(&mut stipple);
         self.stipples.push(stipple);
     }
 
-    pub(crate) fn stipples(&self) -> impl Iterator<Item = &StippleGate<'a>> {
+    pub(crate) fn push_stipples(&mut self, stipples: StippleGate<'a, B>) {
+        self.stipples.push(stipples);
+    }
+
+    /// The stipple batches, in the order they are painted
+    pub fn stipples(&self) -> impl Iterator<Item = &StippleGate<'a, B>> {
         self.stipples.iter()
     }
+
+    /// The stipple batches, in the order they are painted
+    pub fn stipples_mut(&mut self) -> impl Iterator<Item = &mut StippleGate<'a, B>> {
+        self.stipples.iter_mut()
+    }
+
+    /// The layer opacity
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    /// The blend mode used to composite the layer
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
 }
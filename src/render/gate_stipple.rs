@@ -1,33 +1,58 @@
+use crate::backend::{DaliBackend, GlBackend};
+use crate::blend::BlendMode;
 use crate::stipple::Stipple;
 use crate::texture::TextureHandle;
-use crate::MaskHandle;
+use crate::{MaskHandle, WindowSurface};
 
 /// Collects Stipple instances from the user, and provides an owned vec to LayerGate when the user has finished generating instances.
-pub struct StippleGate<'t> {
-    pub(crate) mask: &'t MaskHandle,
-    pub(crate) texture: Option<&'t TextureHandle>,
+pub struct StippleGate<'t, B: DaliBackend = GlBackend<WindowSurface>> {
+    pub(crate) mask: &'t MaskHandle<B>,
+    pub(crate) texture: Option<&'t TextureHandle<B>>,
+    pub(crate) blend_mode: BlendMode,
     stipples: Vec<Stipple>,
 }
 
-impl<'t> StippleGate<'t> {
-    pub fn new(mask: &'t MaskHandle) -> StippleGate {
+impl<'t, B: DaliBackend> StippleGate<'t, B> {
+    pub fn new(mask: &'t MaskHandle<B>) -> StippleGate<'t, B> {
         StippleGate {
             mask,
             texture: None,
+            blend_mode: BlendMode::default(),
             stipples: Vec::new(),
         }
     }
 
-    pub fn new_with_texture(mask: &'t MaskHandle, texture: &'t TextureHandle) -> StippleGate<'t> {
+    pub fn new_with_texture(
+        mask: &'t MaskHandle<B>,
+        texture: &'t TextureHandle<B>,
+    ) -> StippleGate<'t, B> {
         StippleGate {
             mask,
             texture: Some(texture),
+            blend_mode: BlendMode::default(),
             stipples: Vec::new(),
         }
     }
 
-    pub(crate) fn instances(&self) -> impl Iterator<Item = &Stipple> {
-        self.stipples.iter()
+    /// The stipples drawn in this batch
+    pub fn instances(&self) -> &[Stipple] {
+        &self.stipples
+    }
+
+    /// The stipples drawn in this batch, which can be modified, reordered, or removed between renders
+    pub fn instances_mut(&mut self) -> &mut Vec<Stipple> {
+        &mut self.stipples
+    }
+
+    /// The blend mode for all stipples in this gate
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Sets the blend mode for all stipples in this gate
+    /// Default: BlendMode::Normal
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
     }
 
     pub fn draw(&mut self, stipple: Stipple) {
@@ -1,5 +1,10 @@
+pub(crate) mod batch;
 pub(crate) mod gate_canvas;
 pub(crate) mod gate_layer;
 pub(crate) mod gate_stipple;
 pub(crate) mod pipeline;
-pub(in crate::render) mod semantics;
+pub(crate) mod progress;
+pub(crate) mod scene;
+pub(crate) mod semantics;
+pub(crate) mod stats;
+pub(crate) mod stream;
@@ -1,22 +1,42 @@
-use image::Rgba;
-use luminance::blending::Equation::Additive;
-use luminance::blending::Factor::{One, SrcAlphaComplement};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
 use luminance::context::GraphicsContext;
-use luminance::depth_test::DepthComparison;
-use luminance::framebuffer::{ColorSlot, Framebuffer};
-use luminance::pixel::{R32F, RGBA32F};
+use luminance::framebuffer::Framebuffer;
+use luminance::pixel::R32F;
 use luminance::render_state::RenderState;
-use luminance::tess::{Mode, Tess, TessBuilder, TessSlice};
-use luminance::texture::{Dim2, Flat, GenMipmaps, MagFilter, MinFilter, Sampler, Texture, Wrap};
-use luminance_glfw::{Action, GlfwSurface, Key, Surface, WindowEvent};
+use luminance::texture::{Dim2, Flat};
+use luminance_glfw::{Action, Key, WindowEvent};
 
+use crate::backend::{gl, BackendFrame, DaliBackend, GlBackend, Tile, INSTANCE_CHUNK_SIZE};
+use crate::background::{self, Background};
+use crate::blend::BlendMode;
+use crate::clip::ClipHandle;
 use crate::colormap::ColormapHandle;
+use crate::dither::Dither;
+use crate::encode::{self, ImageFileFormat};
+use crate::error::DaliError;
+use crate::mask::MaskFit;
+use crate::render::batch;
 use crate::render::gate_canvas::CanvasGate;
 use crate::render::gate_layer::LayerGate;
-use crate::render::semantics::stipple;
+use crate::render::progress::{CancelToken, RenderProgress, RenderTracker, Tracking};
+use crate::render::scene::Scene;
+use crate::render::stats::RenderStats;
+use crate::render::stream::CanvasStream;
+use crate::scene_file::{AssetKind, SceneAssets, SceneFile};
 use crate::texture::TextureHandle;
+use crate::window::PreviewSurface;
 use crate::{MaskHandle, Stipple, TextureRenderer};
-use std::collections::HashMap;
+
+/// Image files are read back and encoded in strips with this many rows
+const STRIP_ROWS: u32 = 256;
 
 pub enum PreviewAction {
     Escape,
@@ -26,32 +46,130 @@ pub enum PreviewAction {
 /// Launches and executes end-to-end Dali renders.
 /// [preview_canvas] allows live previews, and
 /// [render_canvas] returns image-rs buffers.
-pub struct DaliPipeline<C> {
-    context: C,
-    image_buffers: HashMap<[u32; 2], Framebuffer<Flat, Dim2, RGBA32F, ()>>,
+///
+/// The pipeline is generic over the [DaliBackend] that uploads textures and draws stipples.
+pub struct DaliPipeline<B: DaliBackend> {
+    // backend resources must be dropped before the backend that owns them
+    targets: HashMap<[u32; 2], B::Target>,
+    // isolated layers are rendered into these targets, before they are composited onto the canvas
+    layer_targets: HashMap<[u32; 2], Vec<B::Target>>,
+    // a blank mask, for painting background colormaps over the whole canvas
+    background_mask: Option<B::Mask>,
+    // encodes image files on another thread, while the next strip is rendered
+    background_encoding: bool,
+    dither: Dither,
+    transparent: bool,
+    mask_fit: MaskFit,
+    // progress reporting, cancellation, and the batch count and stats of the last render
+    tracking: Tracking,
+    backend: B,
 }
 
-impl DaliPipeline<GlfwSurface> {
-    pub(crate) fn new(context: GlfwSurface) -> DaliPipeline<GlfwSurface> {
+impl<B: DaliBackend> DaliPipeline<B> {
+    /// Creates a pipeline which renders with the given backend
+    pub fn new(backend: B) -> DaliPipeline<B> {
         DaliPipeline {
-            context,
-            image_buffers: HashMap::new(),
+            targets: HashMap::new(),
+            layer_targets: HashMap::new(),
+            background_mask: None,
+            background_encoding: true,
+            dither: Dither::default(),
+            transparent: false,
+            mask_fit: MaskFit::default(),
+            tracking: Tracking::default(),
+            backend,
         }
     }
 
-    fn colormap_sampler() -> Sampler {
-        // the colormap is likely to be smaller than the output image size (on print quality images)
-        // so the mag filter really needs interpolation.
-        Sampler {
-            min_filter: MinFilter::LinearMipmapLinear,
-            mag_filter: MagFilter::Linear,
-            wrap_s: Wrap::MirroredRepeat,
-            wrap_t: Wrap::MirroredRepeat,
-            ..Sampler::default()
-        }
+    /// The number of stipple batches drawn by the last render, including isolated layers.
+    /// Consecutive stipple gates which share a mask, texture and blend mode are drawn as one batch.
+    /// Tiled renders count each batch once, and streamed gates count one batch for each blend mode.
+    pub fn batch_count(&self) -> usize {
+        self.tracking.batch_count
+    }
+
+    /// Encodes image files on a background thread, so encoding overlaps with rendering.
+    /// The renderer waits if the encoder falls behind, so at most a few strips are held in memory.
+    /// Default: true
+    pub fn set_background_encoding(&mut self, background_encoding: bool) {
+        self.background_encoding = background_encoding;
+    }
+
+    /// Sets how colors are quantized to 8 bits, in render_canvas and image files
+    /// Default: Dither::None
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    /// Keeps the alpha channel in rendered images, so uncovered paper is transparent.
+    /// Colors are un-premultiplied, so they can be composited over other images.
+    /// JPEG files have no alpha channel, so they are always opaque.
+    /// Default: false, which paints the canvas over white paper, with full opacity
+    pub fn set_transparent(&mut self, transparent: bool) {
+        self.transparent = transparent;
+    }
+
+    /// Sets how non-square images are fit into the square stipple, when masks and textures are loaded.
+    /// Default: MaskFit::Pad
+    pub fn set_mask_fit(&mut self, mask_fit: MaskFit) {
+        self.mask_fit = mask_fit;
+    }
+
+    /// Calls the callback with the progress of each render, after each chunk of stipples, and each layer
+    pub fn set_progress_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(&RenderProgress) + 'static,
+    {
+        self.tracking.callback = Some(Box::new(callback));
+    }
+
+    /// Stops reporting progress
+    pub fn clear_progress_callback(&mut self) {
+        self.tracking.callback = None;
     }
 
-    pub fn colormap<F>(&mut self, size: [u32; 2], lambda: F) -> ColormapHandle
+    /// Checks the token between chunks of stipples, and stops the render if it has been cancelled.
+    /// Cancelled renders return DaliError::Cancelled, so use the try_ variants of the render methods.
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.tracking.cancel = Some(cancel_token);
+    }
+
+    /// Collects [RenderStats] during each render, which are returned by render_stats.
+    /// Default: false
+    pub fn set_collect_stats(&mut self, collect_stats: bool) {
+        self.tracking.collect_stats = collect_stats;
+    }
+
+    /// The stats of the last render, if set_collect_stats is enabled
+    pub fn render_stats(&self) -> Option<&RenderStats> {
+        self.tracking.stats.as_ref()
+    }
+
+    /// The backend used for rendering
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// The backend used for rendering
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    pub fn colormap<F>(&mut self, size: [u32; 2], lambda: F) -> ColormapHandle<B>
+    where
+        F: Fn(f32, f32) -> [f32; 4],
+    {
+        self.try_colormap(size, lambda)
+            .expect("Failed to create colormap texture")
+    }
+
+    /// Generates a colormap from a function of the normalized (x, y) texel position
+    /// Returns an error if the texture can't be allocated or uploaded
+    pub fn try_colormap<F>(
+        &mut self,
+        size: [u32; 2],
+        lambda: F,
+    ) -> Result<ColormapHandle<B>, DaliError>
     where
         F: Fn(f32, f32) -> [f32; 4],
     {
@@ -70,138 +188,979 @@ impl DaliPipeline<GlfwSurface> {
             }
         }
 
-        // TODO: look at samplers.
-        let texture: Texture<Flat, Dim2, RGBA32F> =
-            Texture::new(&mut self.context, size, 0, Self::colormap_sampler())
-                .expect("Failed to create colormap texture");
+        let texture = self.backend.upload_colormap(size, buffer.as_slice())?;
+        Ok(ColormapHandle {
+            texture,
+            path: None,
+        })
+    }
+
+    pub fn colormap_from_image(&mut self, image: image::RgbaImage) -> ColormapHandle<B> {
+        self.try_colormap_from_image(image)
+            .expect("Should have generated colormap")
+    }
+
+    /// Uploads an image as a colormap
+    /// Returns an error if the texture can't be allocated or uploaded
+    pub fn try_colormap_from_image(
+        &mut self,
+        image: image::RgbaImage,
+    ) -> Result<ColormapHandle<B>, DaliError> {
+        let dims = image.dimensions();
+        let vec = normalize(image.into_raw());
+
+        let texture = self
+            .backend
+            .upload_colormap([dims.0, dims.1], vec.as_slice())?;
+        Ok(ColormapHandle {
+            texture,
+            path: None,
+        })
+    }
+
+    pub fn mask_from_image(&mut self, image: image::GrayImage, mipmaps: usize) -> MaskHandle<B> {
+        self.try_mask_from_image(image, mipmaps)
+            .expect("Should have generated mask")
+    }
+
+    /// Uploads a greyscale image as a stipple mask.  Non-square images are fit with the pipeline's [MaskFit]
+    /// Returns an error if the texture can't be allocated or uploaded
+    pub fn try_mask_from_image(
+        &mut self,
+        image: image::GrayImage,
+        mipmaps: usize,
+    ) -> Result<MaskHandle<B>, DaliError> {
+        self.fit_mask(image, mipmaps, self.mask_fit)
+    }
+
+    fn fit_mask(
+        &mut self,
+        image: image::GrayImage,
+        mipmaps: usize,
+        fit: MaskFit,
+    ) -> Result<MaskHandle<B>, DaliError> {
+        let image = fit.apply(image, 0);
+        let dims = image.dimensions();
+        let vec = normalize(image.into_raw());
+
+        let mask = self
+            .backend
+            .upload_mask([dims.0, dims.1], vec.as_slice(), mipmaps)?;
+        Ok(MaskHandle {
+            mask,
+            path: None,
+            mipmaps,
+            fit,
+        })
+    }
+
+    pub fn clip_from_image(&mut self, image: image::GrayImage) -> ClipHandle<B> {
+        self.try_clip_from_image(image)
+            .expect("Should have generated clip")
+    }
+
+    /// Uploads a greyscale image as a clip mask, which is stretched over the canvas
+    /// Returns an error if the texture can't be allocated or uploaded
+    pub fn try_clip_from_image(
+        &mut self,
+        image: image::GrayImage,
+    ) -> Result<ClipHandle<B>, DaliError> {
+        let dims = image.dimensions();
+        let vec = normalize(image.into_raw());
+
+        let clip = self
+            .backend
+            .upload_mask([dims.0, dims.1], vec.as_slice(), 0)?;
+        Ok(ClipHandle { clip, path: None })
+    }
+
+    pub fn texture_from_image(
+        &mut self,
+        image: image::GrayImage,
+        mipmaps: usize,
+    ) -> TextureHandle<B> {
+        self.try_texture_from_image(image, mipmaps)
+            .expect("Should have generated texture")
+    }
 
-        texture
-            .upload_raw(GenMipmaps::No, buffer.as_slice())
-            .expect("Texture should have uploaded");
-        ColormapHandle { texture }
+    /// Uploads a greyscale image as a stipple texture.  Non-square images are fit with the pipeline's [MaskFit]
+    /// Returns an error if the texture can't be allocated or uploaded
+    pub fn try_texture_from_image(
+        &mut self,
+        image: image::GrayImage,
+        mipmaps: usize,
+    ) -> Result<TextureHandle<B>, DaliError> {
+        self.fit_texture(image, mipmaps, self.mask_fit)
     }
 
-    // TODO: share code w/ texture_from_image
-    pub fn colormap_from_image(&mut self, image: image::RgbaImage) -> ColormapHandle {
+    fn fit_texture(
+        &mut self,
+        image: image::GrayImage,
+        mipmaps: usize,
+        fit: MaskFit,
+    ) -> Result<TextureHandle<B>, DaliError> {
+        let pad = mean(&image);
+        let image = fit.apply(image, pad);
         let dims = image.dimensions();
+        let vec = normalize(image.into_raw());
+
+        let texture = self
+            .backend
+            .upload_texture([dims.0, dims.1], vec.as_slice(), mipmaps)?;
+        Ok(TextureHandle {
+            texture,
+            path: None,
+            mipmaps,
+            fit,
+        })
+    }
+
+    pub fn colormap_from_file<P: AsRef<Path>>(&mut self, path: P) -> ColormapHandle<B> {
+        self.try_colormap_from_file(path)
+            .expect("Should have loaded colormap")
+    }
+
+    /// Loads an image file as a colormap.  The path is recorded in scene files
+    /// Returns an error if the image can't be decoded, or the texture can't be allocated or uploaded
+    pub fn try_colormap_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<ColormapHandle<B>, DaliError> {
+        let image = image::open(path.as_ref())?.to_rgba();
+        let mut colormap = self.try_colormap_from_image(image)?;
+        colormap.path = Some(path.as_ref().to_path_buf());
+        Ok(colormap)
+    }
+
+    pub fn mask_from_file<P: AsRef<Path>>(&mut self, path: P, mipmaps: usize) -> MaskHandle<B> {
+        self.try_mask_from_file(path, mipmaps)
+            .expect("Should have loaded mask")
+    }
+
+    /// Loads a greyscale image file as a stipple mask.  The path is recorded in scene files
+    /// Returns an error if the image can't be decoded, or the texture can't be allocated or uploaded
+    pub fn try_mask_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        mipmaps: usize,
+    ) -> Result<MaskHandle<B>, DaliError> {
+        self.load_mask(path.as_ref(), mipmaps, self.mask_fit)
+    }
+
+    fn load_mask(
+        &mut self,
+        path: &Path,
+        mipmaps: usize,
+        fit: MaskFit,
+    ) -> Result<MaskHandle<B>, DaliError> {
+        let image = image::open(path)?.to_luma();
+        let mut mask = self.fit_mask(image, mipmaps, fit)?;
+        mask.path = Some(path.to_path_buf());
+        Ok(mask)
+    }
+
+    pub fn texture_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        mipmaps: usize,
+    ) -> TextureHandle<B> {
+        self.try_texture_from_file(path, mipmaps)
+            .expect("Should have loaded texture")
+    }
+
+    /// Loads a greyscale image file as a stipple texture.  The path is recorded in scene files
+    /// Returns an error if the image can't be decoded, or the texture can't be allocated or uploaded
+    pub fn try_texture_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        mipmaps: usize,
+    ) -> Result<TextureHandle<B>, DaliError> {
+        self.load_texture(path.as_ref(), mipmaps, self.mask_fit)
+    }
+
+    fn load_texture(
+        &mut self,
+        path: &Path,
+        mipmaps: usize,
+        fit: MaskFit,
+    ) -> Result<TextureHandle<B>, DaliError> {
+        let image = image::open(path)?.to_luma();
+        let mut texture = self.fit_texture(image, mipmaps, fit)?;
+        texture.path = Some(path.to_path_buf());
+        Ok(texture)
+    }
+
+    pub fn clip_from_file<P: AsRef<Path>>(&mut self, path: P) -> ClipHandle<B> {
+        self.try_clip_from_file(path)
+            .expect("Should have loaded clip")
+    }
+
+    /// Loads a greyscale image file as a clip mask.  The path is recorded in scene files
+    /// Returns an error if the image can't be decoded, or the texture can't be allocated or uploaded
+    pub fn try_clip_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<ClipHandle<B>, DaliError> {
+        let image = image::open(path.as_ref())?.to_luma();
+        let mut clip = self.try_clip_from_image(image)?;
+        clip.path = Some(path.as_ref().to_path_buf());
+        Ok(clip)
+    }
+
+    /// Loads the assets referenced by a scene file, so the scene can be replayed with [SceneAssets::scene]
+    pub fn load_scene_assets(&mut self, file: &SceneFile) -> SceneAssets<B> {
+        self.try_load_scene_assets(file)
+            .expect("Should have loaded scene assets")
+    }
+
+    /// Loads the assets referenced by a scene file, so the scene can be replayed with [SceneAssets::scene].
+    /// Relative asset paths are resolved against the current directory.
+    /// Returns an error if an asset can't be loaded
+    pub fn try_load_scene_assets(&mut self, file: &SceneFile) -> Result<SceneAssets<B>, DaliError> {
+        let mut assets = SceneAssets::new();
+        for asset in &file.assets {
+            let path = asset.path.as_path();
+            match asset.kind {
+                AssetKind::Colormap => {
+                    let colormap = self.try_colormap_from_file(path)?;
+                    assets.colormaps.insert(asset.path.clone(), colormap);
+                }
+                AssetKind::Mask { mipmaps, fit } => {
+                    let mask = self.load_mask(path, mipmaps, fit)?;
+                    assets.masks.insert(asset.path.clone(), mask);
+                }
+                AssetKind::Texture { mipmaps, fit } => {
+                    let texture = self.load_texture(path, mipmaps, fit)?;
+                    assets.textures.insert(asset.path.clone(), texture);
+                }
+                AssetKind::Clip => {
+                    let clip = self.try_clip_from_file(path)?;
+                    assets.clips.insert(asset.path.clone(), clip);
+                }
+            }
+        }
+
+        Ok(assets)
+    }
+
+    /// Renders to an offscreen target, and returns the result as an RgbaImage
+    /// TODO: add feature flag for image-rs dependency
+    pub fn render_canvas<'a, F>(&mut self, size: [u32; 2], callback: F) -> image::RgbaImage
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        self.try_render_canvas(size, callback)
+            .expect("Should have rendered canvas")
+    }
+
+    /// Renders to an offscreen target, and returns the result as an RgbaImage
+    /// Returns an error if the target can't be allocated (e.g. if it exceeds GPU memory), or rendering fails
+    pub fn try_render_canvas<'a, F>(
+        &mut self,
+        size: [u32; 2],
+        callback: F,
+    ) -> Result<image::RgbaImage, DaliError>
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        let scene = Scene::from_fn(callback);
+        self.try_render_scene(size, &scene)
+    }
+
+    /// Renders to an offscreen target, drawing stipples as they are generated, and returns the result as an RgbaImage.
+    /// Memory use is constant, regardless of the number of stipples.  See [CanvasStream]
+    pub fn stream_canvas<F>(&mut self, size: [u32; 2], callback: F) -> image::RgbaImage
+    where
+        F: FnOnce(&mut CanvasStream<B>),
+    {
+        self.try_stream_canvas(size, callback)
+            .expect("Should have streamed canvas")
+    }
+
+    /// Renders to an offscreen target, drawing stipples as they are generated, and returns the result as an RgbaImage
+    /// Returns an error if the target can't be allocated (e.g. if it exceeds GPU memory), or rendering fails
+    pub fn try_stream_canvas<F>(
+        &mut self,
+        size: [u32; 2],
+        callback: F,
+    ) -> Result<image::RgbaImage, DaliError>
+    where
+        F: FnOnce(&mut CanvasStream<B>),
+    {
+        let target = match self.targets.entry(size) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.backend.create_target(size)?),
+        };
+
+        // render_frame accepts an FnMut, but the generator only runs once
+        let mut callback = Some(callback);
+        let backend = &mut self.backend;
+        let raw_texels = self.tracking.track(|tracker| {
+            backend.render_frame(target, Tile::whole(size), background::PAPER, &mut |frame| {
+                let mut canvas = CanvasStream::new(frame, tracker);
+                if let Some(callback) = callback.take() {
+                    callback(&mut canvas);
+                }
+                canvas.finish()
+            })?;
+
+            let start = Instant::now();
+            let raw_texels = backend.read_target(target)?;
+            tracker.add_readback_time(start.elapsed());
+            Ok(raw_texels)
+        })?;
+
+        let start = Instant::now();
+        let texels = to_rows(size, raw_texels, self.transparent);
+        let image = to_image(Tile::whole(size), texels, self.dither);
+        self.tracking.add_conversion_time(start.elapsed());
+        Ok(image)
+    }
+
+    /// Renders a scene to an offscreen target, and returns the result as an RgbaImage.
+    /// The scene can be rendered again, at any size.
+    pub fn render_scene(&mut self, size: [u32; 2], scene: &Scene<B>) -> image::RgbaImage {
+        self.try_render_scene(size, scene)
+            .expect("Should have rendered scene")
+    }
+
+    /// Renders a scene to an offscreen target, and returns the result as an RgbaImage
+    /// Returns an error if the target can't be allocated (e.g. if it exceeds GPU memory), or rendering fails
+    pub fn try_render_scene(
+        &mut self,
+        size: [u32; 2],
+        scene: &Scene<B>,
+    ) -> Result<image::RgbaImage, DaliError> {
+        self.try_render_scene_tiled(size, size, scene)
+    }
+
+    /// Renders a canvas in tiles, and stitches them into an RgbaImage.
+    /// Each tile is rendered into its own target, so the canvas can be larger than the GPU's maximum texture size.
+    pub fn render_canvas_tiled<'a, F>(
+        &mut self,
+        size: [u32; 2],
+        tile_size: [u32; 2],
+        callback: F,
+    ) -> image::RgbaImage
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        self.try_render_canvas_tiled(size, tile_size, callback)
+            .expect("Should have rendered canvas")
+    }
+
+    /// Renders a canvas in tiles, and stitches them into an RgbaImage
+    /// Returns an error if a tile target can't be allocated, or rendering fails
+    pub fn try_render_canvas_tiled<'a, F>(
+        &mut self,
+        size: [u32; 2],
+        tile_size: [u32; 2],
+        callback: F,
+    ) -> Result<image::RgbaImage, DaliError>
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        let scene = Scene::from_fn(callback);
+        self.try_render_scene_tiled(size, tile_size, &scene)
+    }
+
+    /// Renders a scene in tiles, and stitches them into an RgbaImage
+    pub fn render_scene_tiled(
+        &mut self,
+        size: [u32; 2],
+        tile_size: [u32; 2],
+        scene: &Scene<B>,
+    ) -> image::RgbaImage {
+        self.try_render_scene_tiled(size, tile_size, scene)
+            .expect("Should have rendered scene")
+    }
+
+    /// Renders a scene in tiles, and stitches them into an RgbaImage
+    /// Returns an error if a tile target can't be allocated, or rendering fails
+    pub fn try_render_scene_tiled(
+        &mut self,
+        size: [u32; 2],
+        tile_size: [u32; 2],
+        scene: &Scene<B>,
+    ) -> Result<image::RgbaImage, DaliError> {
+        let dither = self.dither;
+        self.try_render_stitched(size, tile_size, scene, |tile, texels| {
+            to_image(tile, texels, dither)
+        })
+    }
+
+    /// Renders to an offscreen target, and returns the result with 16 bits per channel
+    pub fn render_canvas_16<'a, F>(
+        &mut self,
+        size: [u32; 2],
+        callback: F,
+    ) -> image::ImageBuffer<image::Rgba<u16>, Vec<u16>>
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        self.try_render_canvas_16(size, callback)
+            .expect("Should have rendered canvas")
+    }
+
+    /// Renders to an offscreen target, and returns the result with 16 bits per channel
+    /// Returns an error if the target can't be allocated (e.g. if it exceeds GPU memory), or rendering fails
+    pub fn try_render_canvas_16<'a, F>(
+        &mut self,
+        size: [u32; 2],
+        callback: F,
+    ) -> Result<image::ImageBuffer<image::Rgba<u16>, Vec<u16>>, DaliError>
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        let scene = Scene::from_fn(callback);
+        self.try_render_scene_16(size, &scene)
+    }
+
+    /// Renders a scene to an offscreen target, and returns the result with 16 bits per channel
+    pub fn render_scene_16(
+        &mut self,
+        size: [u32; 2],
+        scene: &Scene<B>,
+    ) -> image::ImageBuffer<image::Rgba<u16>, Vec<u16>> {
+        self.try_render_scene_16(size, scene)
+            .expect("Should have rendered scene")
+    }
+
+    /// Renders a scene to an offscreen target, and returns the result with 16 bits per channel
+    /// Returns an error if the target can't be allocated (e.g. if it exceeds GPU memory), or rendering fails
+    pub fn try_render_scene_16(
+        &mut self,
+        size: [u32; 2],
+        scene: &Scene<B>,
+    ) -> Result<image::ImageBuffer<image::Rgba<u16>, Vec<u16>>, DaliError> {
+        self.try_render_stitched(size, size, scene, |tile, texels| {
+            to_image_16(tile.size, texels)
+        })
+    }
+
+    /// Renders to an offscreen target, and returns the float texels, without quantizing them
+    pub fn render_canvas_f32<'a, F>(
+        &mut self,
+        size: [u32; 2],
+        callback: F,
+    ) -> image::ImageBuffer<image::Rgba<f32>, Vec<f32>>
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        self.try_render_canvas_f32(size, callback)
+            .expect("Should have rendered canvas")
+    }
+
+    /// Renders to an offscreen target, and returns the float texels, without quantizing them
+    /// Returns an error if the target can't be allocated (e.g. if it exceeds GPU memory), or rendering fails
+    pub fn try_render_canvas_f32<'a, F>(
+        &mut self,
+        size: [u32; 2],
+        callback: F,
+    ) -> Result<image::ImageBuffer<image::Rgba<f32>, Vec<f32>>, DaliError>
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        let scene = Scene::from_fn(callback);
+        self.try_render_scene_f32(size, &scene)
+    }
+
+    /// Renders a scene to an offscreen target, and returns the float texels, without quantizing them
+    pub fn render_scene_f32(
+        &mut self,
+        size: [u32; 2],
+        scene: &Scene<B>,
+    ) -> image::ImageBuffer<image::Rgba<f32>, Vec<f32>> {
+        self.try_render_scene_f32(size, scene)
+            .expect("Should have rendered scene")
+    }
 
-        // TODO: look at samplers.
-        let texture: Texture<Flat, Dim2, RGBA32F> = Texture::new(
-            &mut self.context,
-            [dims.0, dims.1],
-            0,
-            Self::colormap_sampler(),
-        )
-        .expect("Should have generated texture");
+    /// Renders a scene to an offscreen target, and returns the float texels, without quantizing them
+    /// Returns an error if the target can't be allocated (e.g. if it exceeds GPU memory), or rendering fails
+    pub fn try_render_scene_f32(
+        &mut self,
+        size: [u32; 2],
+        scene: &Scene<B>,
+    ) -> Result<image::ImageBuffer<image::Rgba<f32>, Vec<f32>>, DaliError> {
+        self.try_render_stitched(size, size, scene, |tile, texels| {
+            to_image_f32(tile.size, texels)
+        })
+    }
 
-        let vec = image.into_raw();
-        let vec: Vec<f32> = vec.into_iter().map(|e| (e as f32) / 255.0).collect();
+    /// Renders the canvas in tiles, converts the rows of each tile to an image, and stitches the tile images together
+    fn try_render_stitched<P, C>(
+        &mut self,
+        size: [u32; 2],
+        tile_size: [u32; 2],
+        scene: &Scene<B>,
+        convert: C,
+    ) -> Result<image::ImageBuffer<P, Vec<P::Subpixel>>, DaliError>
+    where
+        P: image::Pixel + 'static,
+        C: Fn(Tile, Vec<f32>) -> image::ImageBuffer<P, Vec<P::Subpixel>>,
+    {
+        let mut canvas = None;
+        let mut conversion = Duration::default();
+        let transparent = self.transparent;
+        self.try_render_tiles(size, tile_size, scene, |tile, reader| {
+            let texels = reader.read()?;
+            let start = Instant::now();
+            let image = convert(tile, to_rows(tile.size, texels, transparent));
+            if tile.size == size {
+                canvas = Some(image);
+            } else {
+                let canvas =
+                    canvas.get_or_insert_with(|| image::ImageBuffer::new(size[0], size[1]));
+                image::imageops::replace(canvas, &image, tile.offset[0], tile.offset[1]);
+            }
+            conversion += start.elapsed();
+            Ok(())
+        })?;
+        self.tracking.add_conversion_time(conversion);
 
-        texture
-            .upload_raw(GenMipmaps::No, vec.as_slice())
-            .expect("Should have uploaded texture");
+        Ok(canvas.unwrap_or_else(|| image::ImageBuffer::new(size[0], size[1])))
+    }
 
-        ColormapHandle { texture }
+    /// Renders a canvas into an image file, and reads it back in strips of rows which are encoded as they are read.
+    /// The canvas is rendered once, unless it's larger than the backend's targets, and then it's rendered in tiles.
+    /// The whole image is never held in memory (except for JPEG, which needs the whole image to encode).
+    pub fn render_canvas_to_file<'a, P, F>(
+        &mut self,
+        size: [u32; 2],
+        path: P,
+        format: ImageFileFormat,
+        callback: F,
+    ) where
+        P: AsRef<Path>,
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        self.try_render_canvas_to_file(size, path, format, callback)
+            .expect("Should have rendered canvas to file")
+    }
+
+    /// Renders a canvas into an image file, in strips of rows which are encoded as they are read
+    /// Returns an error if the file can't be written, a tile target can't be allocated, or rendering fails
+    pub fn try_render_canvas_to_file<'a, P, F>(
+        &mut self,
+        size: [u32; 2],
+        path: P,
+        format: ImageFileFormat,
+        callback: F,
+    ) -> Result<(), DaliError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut CanvasGate<'a, B>),
+        B: 'a,
+    {
+        let scene = Scene::from_fn(callback);
+        self.try_render_scene_to_file(size, path, format, &scene)
+    }
+
+    /// Renders a scene into an image file, in strips of rows which are encoded as they are read
+    pub fn render_scene_to_file<P: AsRef<Path>>(
+        &mut self,
+        size: [u32; 2],
+        path: P,
+        format: ImageFileFormat,
+        scene: &Scene<B>,
+    ) {
+        self.try_render_scene_to_file(size, path, format, scene)
+            .expect("Should have rendered scene to file")
     }
 
-    fn texture_sampler() -> Sampler {
-        // we want all the interpolation we can get.
-        // this will prevent small blocky areas in the output
-        Sampler {
-            min_filter: MinFilter::LinearMipmapLinear,
-            mag_filter: MagFilter::Linear,
-            ..Sampler::default()
+    /// Renders a scene into an image file, in strips of rows which are encoded as they are read
+    /// Returns an error if the file can't be written, a tile target can't be allocated, or rendering fails.
+    /// The file is removed if there is an error.
+    pub fn try_render_scene_to_file<P: AsRef<Path>>(
+        &mut self,
+        size: [u32; 2],
+        path: P,
+        format: ImageFileFormat,
+        scene: &Scene<B>,
+    ) -> Result<(), DaliError> {
+        let path = path.as_ref();
+        let file = BufWriter::new(File::create(path)?);
+        let result = self.try_encode_scene(file, size, format, scene);
+        if result.is_err() {
+            // a partial image can look like a finished render
+            let _ = fs::remove_file(path);
         }
+        result
     }
 
-    fn to_square(mut image: image::GrayImage) -> image::GrayImage {
-        let (w, h) = image.dimensions();
-        if w == h {
-            return image;
+    /// Renders a scene in strips, and encodes them into the file, on a background thread if it's enabled
+    fn try_encode_scene(
+        &mut self,
+        file: BufWriter<File>,
+        size: [u32; 2],
+        format: ImageFileFormat,
+        scene: &Scene<B>,
+    ) -> Result<(), DaliError> {
+        let transparent = self.transparent && !matches!(format, ImageFileFormat::Jpeg { .. });
+
+        if !self.background_encoding {
+            return encode::encode(file, size, format, |sink| {
+                self.try_render_strips(size, transparent, scene, |strip| sink(&strip))
+            });
         }
 
-        if w > h {
-            let edge_len = (w - h) / 2;
-            println!(
-                "Resizing from {}x{} to {}x{} with bar length {}",
-                w, h, h, h, edge_len
-            );
+        // the channel is bounded, so the renderer can't get far ahead of the encoder
+        let (sender, receiver) = mpsc::sync_channel::<image::RgbaImage>(1);
+        let encoder = thread::spawn(move || {
+            encode::encode(file, size, format, |sink| {
+                receiver.iter().try_for_each(|strip| sink(&strip))
+            })
+        });
+
+        let mut stopped = false;
+        let rendered = self.try_render_strips(size, transparent, scene, |strip| {
+            sender.send(strip).map_err(|_| {
+                stopped = true;
+                DaliError::Encoding("the encoder stopped early".to_string())
+            })
+        });
+        drop(sender);
 
-            let cropped = image::imageops::crop(&mut image, edge_len, 0, h, h);
+        let encoded = encoder.join().unwrap_or_else(|_| {
+            Err(DaliError::Encoding(
+                "the encoder thread panicked".to_string(),
+            ))
+        });
 
-            return cropped.to_image();
-        } else {
-            let bar_len = (h - w) / 2;
-            println!(
-                "Resizing from {}x{} to {}x{} with bar length {}",
-                w, h, w, w, bar_len
-            );
+        if stopped {
+            // the encoder's error explains why the renderer couldn't send strips
+            return encoded.and(rendered);
+        }
+
+        // a failed render ends the strips early, and the encoder only reports the missing rows
+        rendered.and(encoded)
+    }
+
+    /// Renders the canvas, reads it back in strips of rows, and passes each strip to `visit`, from the top of the canvas.
+    /// The canvas is rendered once, unless it's larger than the backend's targets.
+    /// Strips have the full width of the canvas, so canvases wider than a target are passed a row of tiles at a time.
+    fn try_render_strips<V>(
+        &mut self,
+        size: [u32; 2],
+        transparent: bool,
+        scene: &Scene<B>,
+        mut visit: V,
+    ) -> Result<(), DaliError>
+    where
+        V: FnMut(image::RgbaImage) -> Result<(), DaliError>,
+    {
+        let max_size = self.backend.max_target_size();
+        let tile_size = [size[0].min(max_size), size[1].min(max_size)];
+        let mut row = None;
+        let mut conversion = Duration::default();
+        let dither = self.dither;
+
+        self.try_render_tiles(size, tile_size, scene, |tile, reader| {
+            for top in (0..tile.size[1]).step_by(STRIP_ROWS as usize) {
+                let rows = STRIP_ROWS.min(tile.size[1] - top);
+                let texels = reader.read_rows(top, rows)?;
+
+                let start = Instant::now();
+                let strip = Tile {
+                    canvas: tile.canvas,
+                    offset: [tile.offset[0], tile.offset[1] + top],
+                    size: [tile.size[0], rows],
+                };
+                let image = to_image(strip, to_rows(strip.size, texels, transparent), dither);
+                if tile.size[0] == size[0] {
+                    conversion += start.elapsed();
+                    visit(image)?;
+                    continue;
+                }
+
+                let row_image =
+                    row.get_or_insert_with(|| image::RgbaImage::new(size[0], tile.size[1]));
+                image::imageops::replace(row_image, &image, tile.offset[0], top);
+                conversion += start.elapsed();
+            }
 
-            let cropped = image::imageops::crop(&mut image, 0, bar_len, w, w);
+            // the last tile in the row completes the row
+            if tile.size[0] != size[0] && tile.offset[0] + tile.size[0] == size[0] {
+                let row_image = row.take().expect("Row should have been inserted");
+                visit(row_image)?;
+            }
+            Ok(())
+        })?;
+        self.tracking.add_conversion_time(conversion);
+
+        Ok(())
+    }
+
+    /// Renders each tile of the canvas, in rows from the top left, and passes a reader for the tile's texels to `visit`
+    pub(crate) fn try_render_tiles<V>(
+        &mut self,
+        size: [u32; 2],
+        tile_size: [u32; 2],
+        scene: &Scene<B>,
+        mut visit: V,
+    ) -> Result<(), DaliError>
+    where
+        V: FnMut(Tile, &mut TileReader<B>) -> Result<(), DaliError>,
+    {
+        let canvas_gate = scene.canvas();
+        let background = canvas_gate.background();
+        let image = Self::upload_background(&mut self.backend, background)?;
+        let mask = Self::background_mask(&mut self.backend, &mut self.background_mask, background)?;
+
+        let tiles = Tile::split(size, tile_size);
+        let backend = &mut self.backend;
+        let targets = &mut self.targets;
+        let layer_targets = &mut self.layer_targets;
+        self.tracking.track(|tracker| {
+            tracker.add_totals(canvas_gate, tiles.len());
+
+            for (index, tile) in tiles.into_iter().enumerate() {
+                tracker.start_tile(index);
+                let target = match targets.entry(tile.size) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(backend.create_target(tile.size)?),
+                };
+
+                let isolated = Self::render_isolated_layers(
+                    backend,
+                    layer_targets,
+                    tile,
+                    canvas_gate,
+                    tracker,
+                )?;
+
+                backend.render_frame(target, tile, background.clear_color(), &mut |frame| {
+                    Self::draw_background(frame, background, image.as_ref(), mask, size, tracker)?;
+                    Self::draw(frame, canvas_gate.layers(), isolated, tracker)
+                })?;
+
+                let mut reader = TileReader {
+                    backend: &mut *backend,
+                    target,
+                    tile,
+                    tracker: &mut *tracker,
+                };
+                visit(tile, &mut reader)?;
+            }
+
+            Ok(())
+        })
+    }
 
-            return cropped.to_image();
+    /// Renders each isolated layer into its own target, and returns the targets in layer order
+    fn render_isolated_layers<'t, 'a>(
+        backend: &mut B,
+        layer_targets: &'t mut HashMap<[u32; 2], Vec<B::Target>>,
+        tile: Tile,
+        canvas_gate: &CanvasGate<'a, B>,
+        tracker: &mut RenderTracker,
+    ) -> Result<&'t [B::Target], DaliError>
+    where
+        B: 'a,
+    {
+        let isolated: Vec<(usize, &LayerGate<'a, B>)> = canvas_gate
+            .layers()
+            .enumerate()
+            .filter(|(_, layer)| layer.is_isolated())
+            .collect();
+
+        let targets = layer_targets.entry(tile.size).or_insert_with(Vec::new);
+        while targets.len() < isolated.len() {
+            targets.push(backend.create_target(tile.size)?);
         }
+
+        for ((index, layer), target) in isolated.into_iter().zip(targets.iter_mut()) {
+            let start = Instant::now();
+            backend.render_frame(target, tile, background::PAPER, &mut |frame| {
+                Self::draw_layer(frame, layer, tracker)
+            })?;
+            tracker.add_layer_time(index, start.elapsed());
+        }
+
+        Ok(targets.as_slice())
     }
 
-    pub fn mask_from_image(&mut self, image: image::GrayImage, mipmaps: usize) -> MaskHandle {
-        let image = Self::to_square(image);
+    /// Uploads an image background as a colormap
+    fn upload_background(
+        backend: &mut B,
+        background: &Background<B>,
+    ) -> Result<Option<B::Colormap>, DaliError> {
+        match background {
+            Background::Image(image) => {
+                let dims = image.dimensions();
+                let vec = normalize(image.to_vec());
+                let colormap = backend.upload_colormap([dims.0, dims.1], vec.as_slice())?;
+                Ok(Some(colormap))
+            }
+            _ => Ok(None),
+        }
+    }
 
-        let dims = image.dimensions();
-        // TODO: look at samplers.
-        let texture: Texture<Flat, Dim2, R32F> = Texture::new(
-            &mut self.context,
-            [dims.0, dims.1],
-            mipmaps,
-            Self::texture_sampler(),
-        )
-        .expect("Should have generated texture");
+    /// Returns the blank mask, if the background needs to be painted
+    fn background_mask<'t>(
+        backend: &mut B,
+        background_mask: &'t mut Option<B::Mask>,
+        background: &Background<B>,
+    ) -> Result<Option<&'t B::Mask>, DaliError> {
+        if let Background::Color(_) = background {
+            return Ok(None);
+        }
+
+        if background_mask.is_none() {
+            *background_mask = Some(backend.upload_mask([1, 1], &[1.0], 0)?);
+        }
 
-        let vec = image.into_raw();
-        let vec: Vec<f32> = vec.into_iter().map(|e| (e as f32) / 255.0).collect();
+        Ok(background_mask.as_ref())
+    }
 
-        texture
-            .upload_raw(GenMipmaps::Yes, vec.as_slice())
-            .expect("Should have uploaded texture");
+    /// Paints colormap and image backgrounds with a stipple that covers the canvas
+    fn draw_background(
+        frame: &mut dyn BackendFrame<B>,
+        background: &Background<B>,
+        image: Option<&B::Colormap>,
+        mask: Option<&B::Mask>,
+        size: [u32; 2],
+        tracker: &mut RenderTracker,
+    ) -> Result<(), DaliError> {
+        let colormap = match background {
+            Background::Color(_) => None,
+            Background::Colormap(colormap) => Some(&colormap.texture),
+            Background::Image(_) => image,
+        };
 
-        MaskHandle { mask: texture }
+        if let (Some(colormap), Some(mask)) = (colormap, mask) {
+            let aspect = size[0] as f32 / size[1] as f32;
+            let canvas = Stipple::new().with_scale([aspect, 1.0]);
+            frame.draw_stipples(colormap, mask, None, None, BlendMode::Normal, &[canvas])?;
+            tracker.stipple_call(false, false);
+        }
+
+        Ok(())
     }
 
-    pub fn texture_from_image(&mut self, image: image::GrayImage, mipmaps: usize) -> TextureHandle {
-        let image = Self::to_square(image);
+    fn draw<'i, 'a: 'i, I: Iterator<Item = &'i LayerGate<'a, B>>>(
+        frame: &mut dyn BackendFrame<B>,
+        layers: I,
+        isolated: &[B::Target],
+        tracker: &mut RenderTracker,
+    ) -> Result<(), DaliError>
+    where
+        B: 'a,
+    {
+        let mut isolated = isolated.iter();
+        for (index, layer) in layers.enumerate() {
+            let start = Instant::now();
+            if layer.is_isolated() {
+                let target = isolated
+                    .next()
+                    .expect("Isolated layers should have been rendered");
+                frame.composite(target, layer.opacity, layer.blend_mode)?;
+                tracker.composite_call();
+            } else {
+                Self::draw_layer(frame, layer, tracker)?;
+            }
+            tracker.add_layer_time(index, start.elapsed());
+        }
 
-        let dims = image.dimensions();
-        // TODO: look at samplers.
-        let texture: Texture<Flat, Dim2, R32F> = Texture::new(
-            &mut self.context,
-            [dims.0, dims.1],
-            mipmaps,
-            Self::texture_sampler(),
-        )
-        .expect("Should have generated texture");
+        Ok(())
+    }
 
-        let vec = image.into_raw();
-        let vec: Vec<f32> = vec.into_iter().map(|e| (e as f32) / 255.0).collect();
+    /// Draws the stipple gates of a layer, merged into as few batches as the layer allows
+    fn draw_layer<'a>(
+        frame: &mut dyn BackendFrame<B>,
+        layer: &LayerGate<'a, B>,
+        tracker: &mut RenderTracker,
+    ) -> Result<(), DaliError>
+    where
+        B: 'a,
+    {
+        let mut scratch = Vec::new();
+        for batch in batch::batches(layer) {
+            let stipples = batch.gate();
+
+            // batches are submitted in chunks, so long layers report progress, and can be cancelled
+            for chunk in batch.instances(&mut scratch).chunks(INSTANCE_CHUNK_SIZE) {
+                tracker.check_cancelled()?;
+                frame.draw_stipples(
+                    &layer.colormap.texture,
+                    &stipples.mask.mask,
+                    stipples.texture.map(|texture| &texture.texture),
+                    layer.clip.map(|clip| &clip.clip),
+                    stipples.blend_mode,
+                    chunk,
+                )?;
+                tracker.stipple_call(stipples.texture.is_some(), layer.clip.is_some());
+                tracker.stipples_drawn(chunk.len());
+            }
+            tracker.batch_done();
+        }
+        tracker.layer_done();
+
+        Ok(())
+    }
+}
 
-        texture
-            .upload_raw(GenMipmaps::Yes, vec.as_slice())
-            .expect("Should have uploaded texture");
+/// Reads back the texels of a rendered tile, for the visitor of [DaliPipeline::try_render_tiles]
+pub(crate) struct TileReader<'r, B: DaliBackend> {
+    backend: &'r mut B,
+    target: &'r B::Target,
+    tile: Tile,
+    tracker: &'r mut RenderTracker,
+}
 
-        TextureHandle { texture }
+impl<'r, B: DaliBackend> TileReader<'r, B> {
+    /// Reads every texel of the tile, starting with the bottom row
+    pub(crate) fn read(&mut self) -> Result<Vec<f32>, DaliError> {
+        let start = Instant::now();
+        let texels = self.backend.read_target(self.target)?;
+        self.tracker.add_readback_time(start.elapsed());
+        Ok(texels)
     }
 
-    pub fn texture<T: TextureRenderer>(&mut self, texture_renderer: &T) -> TextureHandle {
-        // allocate framebuffer
+    /// Reads `rows` rows of the tile, starting `top` rows from the top, with the bottom row first like [read](TileReader::read)
+    pub(crate) fn read_rows(&mut self, top: u32, rows: u32) -> Result<Vec<f32>, DaliError> {
+        let start = Instant::now();
+        let first = self.tile.size[1] - top - rows;
+        let texels = self.backend.read_target_rows(self.target, first, rows)?;
+        self.tracker.add_readback_time(start.elapsed());
+        Ok(texels)
+    }
+}
+
+impl<C: GraphicsContext> DaliPipeline<GlBackend<C>> {
+    pub fn texture<T: TextureRenderer>(
+        &mut self,
+        texture_renderer: &T,
+    ) -> TextureHandle<GlBackend<C>> {
+        self.try_texture(texture_renderer)
+            .expect("Should have rendered texture")
+    }
 
-        let program = texture_renderer.compile();
+    /// Renders a texture on the GPU
+    /// Returns an error if the shader fails to compile, or the texture can't be allocated
+    pub fn try_texture<T: TextureRenderer>(
+        &mut self,
+        texture_renderer: &T,
+    ) -> Result<TextureHandle<GlBackend<C>>, DaliError> {
+        let context = &mut self.backend.context;
+
+        // allocate framebuffer
+        let program = texture_renderer.try_compile()?;
         let buffer: Framebuffer<Flat, Dim2, R32F, ()> =
-            Framebuffer::new(&mut self.context, texture_renderer.texture_size(), 0)
-                .expect("Should have framebuffer");
+            Framebuffer::new(context, texture_renderer.texture_size(), 0)?;
 
-        let tess = texture_renderer
-            .tesselate(&mut self.context)
-            .expect("Should have tesslated");
+        let tess = texture_renderer.tesselate(context)?;
 
-        let pipeline_builder = &mut self.context.pipeline_builder();
+        let pipeline_builder = &mut context.pipeline_builder();
         pipeline_builder.pipeline(&buffer, [0., 0., 0., 1.], |_pipeline, mut shd_gate| {
             shd_gate.shade(&program, |_, mut rdr_gate| {
                 rdr_gate.render(RenderState::default(), |mut tess_gate| {
@@ -212,73 +1171,128 @@ impl DaliPipeline<GlfwSurface> {
             });
         });
 
-        // TODO: look at samplers.
-        let texture: Texture<Flat, Dim2, R32F> = Texture::new(
-            &mut self.context,
+        let texels: Vec<f32> = buffer.color_slot().get_raw_texels();
+        let texture = self.backend.upload_texture(
             texture_renderer.texture_size(),
+            texels.as_slice(),
             texture_renderer.mipmaps(),
-            Self::texture_sampler(),
-        )
-        .expect("Should have generated texture");
-
-        let texels: Vec<f32> = buffer.color_slot().get_raw_texels();
-        texture
-            .upload_raw(GenMipmaps::Yes, texels.as_slice())
-            .expect("Should have uploaded texture");
-
-        TextureHandle { texture }
+        )?;
+
+        Ok(TextureHandle {
+            texture,
+            path: None,
+            mipmaps: texture_renderer.mipmaps(),
+            fit: MaskFit::default(),
+        })
     }
+}
 
+impl<S: PreviewSurface> DaliPipeline<GlBackend<S>> {
     /// Prepares an interactive window, renders, and shows the result
-    pub fn preview_canvas<'a, F>(&'a mut self, callback: F) -> PreviewAction
+    pub fn preview_canvas<'a, F>(&mut self, callback: F) -> PreviewAction
     where
-        F: FnOnce(&mut CanvasGate<'a>),
+        F: FnOnce(&mut CanvasGate<'a, GlBackend<S>>),
+        S: 'a,
     {
-        let mut back_buffer = self.context.back_buffer().expect("Should have backbuffer");
+        self.try_preview_canvas(callback)
+            .expect("Should have previewed canvas")
+    }
 
-        let mut canvas_gate = CanvasGate::new();
-        callback(&mut canvas_gate);
+    /// Prepares an interactive window, renders, and shows the result
+    /// Returns an error if the window's back buffer is unavailable, or rendering fails
+    pub fn try_preview_canvas<'a, F>(&mut self, callback: F) -> Result<PreviewAction, DaliError>
+    where
+        F: FnOnce(&mut CanvasGate<'a, GlBackend<S>>),
+        S: 'a,
+    {
+        let scene = Scene::from_fn(callback);
+        self.try_preview_scene(&scene)
+    }
 
-        Self::draw(&mut self.context, canvas_gate.layers(), &mut back_buffer);
+    /// Prepares an interactive window, renders the scene, and shows the result
+    pub fn preview_scene(&mut self, scene: &Scene<GlBackend<S>>) -> PreviewAction {
+        self.try_preview_scene(scene)
+            .expect("Should have previewed scene")
+    }
 
-        self.context.swap_buffers();
+    /// Prepares an interactive window, renders the scene, and shows the result
+    /// Returns an error if the window's back buffer is unavailable, or rendering fails
+    pub fn try_preview_scene(
+        &mut self,
+        scene: &Scene<GlBackend<S>>,
+    ) -> Result<PreviewAction, DaliError> {
+        let back_buffer = self.backend.context.preview_buffer()?;
+
+        let canvas_gate = scene.canvas();
+
+        let size = [back_buffer.width(), back_buffer.height()];
+        let background = canvas_gate.background();
+        let image = Self::upload_background(&mut self.backend, background)?;
+        let mask = Self::background_mask(&mut self.backend, &mut self.background_mask, background)?;
+
+        let backend = &mut self.backend;
+        let layer_targets = &mut self.layer_targets;
+        self.tracking.track(|tracker| {
+            tracker.add_totals(canvas_gate, 1);
+            let isolated = Self::render_isolated_layers(
+                backend,
+                layer_targets,
+                Tile::whole(size),
+                canvas_gate,
+                tracker,
+            )?;
+
+            gl::render_frame(
+                &mut backend.context,
+                &mut backend.resources,
+                &back_buffer,
+                Tile::whole(size),
+                background.clear_color(),
+                &mut |frame| {
+                    Self::draw_background(frame, background, image.as_ref(), mask, size, tracker)?;
+                    Self::draw(frame, canvas_gate.layers(), isolated, tracker)
+                },
+            )
+        })?;
+
+        self.backend.context.present();
 
         loop {
             // for all the events on the surface
-            for event in self.context.poll_events() {
+            for event in self.backend.context.preview_events() {
                 match event {
                     WindowEvent::Close | WindowEvent::Key(Key::Escape, _, Action::Release, _) => {
-                        return PreviewAction::Escape
+                        return Ok(PreviewAction::Escape)
                     }
                     WindowEvent::Key(Key::Num1, _, Action::Release, _) => {
-                        return PreviewAction::Rating(1)
+                        return Ok(PreviewAction::Rating(1))
                     }
                     WindowEvent::Key(Key::Num2, _, Action::Release, _) => {
-                        return PreviewAction::Rating(2)
+                        return Ok(PreviewAction::Rating(2))
                     }
                     WindowEvent::Key(Key::Num3, _, Action::Release, _) => {
-                        return PreviewAction::Rating(3)
+                        return Ok(PreviewAction::Rating(3))
                     }
                     WindowEvent::Key(Key::Num4, _, Action::Release, _) => {
-                        return PreviewAction::Rating(4)
+                        return Ok(PreviewAction::Rating(4))
                     }
                     WindowEvent::Key(Key::Num5, _, Action::Release, _) => {
-                        return PreviewAction::Rating(5)
+                        return Ok(PreviewAction::Rating(5))
                     }
                     WindowEvent::Key(Key::Num6, _, Action::Release, _) => {
-                        return PreviewAction::Rating(6)
+                        return Ok(PreviewAction::Rating(6))
                     }
                     WindowEvent::Key(Key::Num7, _, Action::Release, _) => {
-                        return PreviewAction::Rating(7)
+                        return Ok(PreviewAction::Rating(7))
                     }
                     WindowEvent::Key(Key::Num8, _, Action::Release, _) => {
-                        return PreviewAction::Rating(8)
+                        return Ok(PreviewAction::Rating(8))
                     }
                     WindowEvent::Key(Key::Num9, _, Action::Release, _) => {
-                        return PreviewAction::Rating(9)
+                        return Ok(PreviewAction::Rating(9))
                     }
                     WindowEvent::Key(Key::Num0, _, Action::Release, _) => {
-                        return PreviewAction::Rating(10)
+                        return Ok(PreviewAction::Rating(10))
                     }
 
                     WindowEvent::FramebufferSize(_width, _height) => {}
@@ -288,134 +1302,89 @@ impl DaliPipeline<GlfwSurface> {
             }
         }
     }
+}
 
-    /// Renders to an offscreen framebuffer, and returns the result as a DynamicImage
-    /// TODO: add feature flag for image-rs dependency
-    pub fn render_canvas<'a, F>(
-        &'a mut self,
-        size: [u32; 2],
-        callback: F,
-    ) -> image::ImageBuffer<Rgba<u8>, Vec<u8>>
-    where
-        F: FnOnce(&mut CanvasGate<'a>),
-    {
-        let buffers = &mut self.image_buffers;
-        let buffer = match buffers.get_mut(&size) {
-            Some(t) => t,
-            None => {
-                let buffer = Framebuffer::new(&mut self.context, size, 0).unwrap();
-                self.image_buffers.insert(size.clone(), buffer);
-                self.image_buffers.get_mut(&size).unwrap()
-            }
-        };
+/// The mean value of a greyscale image, which pads textures without shifting their tone
+fn mean(image: &image::GrayImage) -> u8 {
+    let sum: u64 = image.pixels().map(|p| p.0[0] as u64).sum();
+    let count = (image.width() as u64 * image.height() as u64).max(1);
+    (sum / count) as u8
+}
 
-        let mut canvas_gate = CanvasGate::new();
-        callback(&mut canvas_gate);
+fn normalize(texels: Vec<u8>) -> Vec<f32> {
+    texels.into_iter().map(|e| (e as f32) / 255.0).collect()
+}
 
-        Self::draw(&mut self.context, canvas_gate.layers(), buffer);
+/// Quantizes texel rows (from to_rows) to 8 bits
+pub(crate) fn to_image(tile: Tile, texels: Vec<f32>, dither: Dither) -> image::RgbaImage {
+    let width = tile.size[0];
+
+    // dithering follows canvas pixels, so tiles line up seamlessly
+    let mut image = image::RgbaImage::new(width, tile.size[1]);
+    for (i, (texel, pixel)) in texels.chunks(4).zip(image.pixels_mut()).enumerate() {
+        let x = tile.offset[0] + i as u32 % width;
+        let y = tile.offset[1] + i as u32 / width;
+        for (channel, value) in pixel.0.iter_mut().zip(texel) {
+            *channel = dither.quantize(*value, x, y);
+        }
+    }
+    image
+}
 
-        let mut raw_texels: Vec<f32> = buffer.color_slot().get_raw_texels();
-        // we need to undo the premultiplied alpha
-        // we *could* divide the color channels by the alpha channel, but the image crate does not
-        // properly handle this if saving to JPEG (which has no alpha support)
-        // instead, let's set the image to full opacity.
+/// Quantizes texel rows (from to_rows) to 16 bits
+pub(crate) fn to_image_16(
+    size: [u32; 2],
+    texels: Vec<f32>,
+) -> image::ImageBuffer<image::Rgba<u16>, Vec<u16>> {
+    let texels: Vec<u16> = texels
+        .into_iter()
+        .map(|e| (e.clamp(0.0, 1.0) * 65535.0).round() as u16)
+        .collect();
+
+    image::ImageBuffer::from_raw(size[0], size[1], texels).expect("Texels should match the size")
+}
 
-        // this makes JPEG, PNG, and Preview output identical.
-        raw_texels.chunks_mut(4).for_each(|chunk| {
-            chunk[3] = 1.0;
-        });
+pub(crate) fn to_image_f32(
+    size: [u32; 2],
+    texels: Vec<f32>,
+) -> image::ImageBuffer<image::Rgba<f32>, Vec<f32>> {
+    image::ImageBuffer::from_raw(size[0], size[1], texels).expect("Texels should match the size")
+}
 
-        let raw_texels: Vec<u8> = raw_texels.into_iter().map(|e| (e * 255.0) as u8).collect();
+/// Reorders raw texels so that the first row is at the top.
+/// Opaque texels keep the color over white paper, with full opacity.
+/// Transparent texels are un-premultiplied, and keep their alpha.
+pub(crate) fn to_rows(size: [u32; 2], mut raw_texels: Vec<f32>, transparent: bool) -> Vec<f32> {
+    // the target holds the color over white paper (D), and the coverage (A)
+    // opaque output is D itself, which makes JPEG, PNG, and Preview output identical.
+    raw_texels.chunks_mut(4).for_each(|chunk| {
+        if transparent {
+            unpremultiply(chunk);
+        } else {
+            chunk[3] = 1.0;
+        }
+    });
 
-        let buffer = image::ImageBuffer::from_raw(size[0], size[1], raw_texels).unwrap();
-        image::imageops::flip_vertical(&buffer)
+    let row = size[0] as usize * 4;
+    let mut rows = Vec::with_capacity(raw_texels.len());
+    for texels in raw_texels.chunks(row.max(1)).rev() {
+        rows.extend_from_slice(texels);
     }
+    rows
+}
 
-    fn draw<'i, 'a: 'i, CS: ColorSlot<Flat, Dim2>, I: Iterator<Item = &'i LayerGate<'a>>>(
-        context: &mut GlfwSurface,
-        layers: I,
-        target_buffer: &mut Framebuffer<Flat, Dim2, CS, ()>,
-    ) {
-        let stipple_program = crate::render::semantics::stipple::compile();
-        let stipple_texture_program = crate::render::semantics::stipple::compile_with_texture();
-
-        const INSTANCE_CHUNK_SIZE: usize = 512;
-        const QUAD: [[f32; 2]; 4] = [[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]];
-
-        let null_instance = Stipple::new().with_scale([0.0, 0.0]).into();
-        let null_instances: Vec<stipple::VertexInstance> = std::iter::repeat(null_instance)
-            .take(INSTANCE_CHUNK_SIZE)
-            .collect();
+/// Converts a texel from color over white paper to straight alpha.
+/// The premultiplied color is D - 1 + A, so the straight color is (D - 1 + A) / A
+fn unpremultiply(texel: &mut [f32]) {
+    let alpha = texel[3];
 
-        let stipple_quad: Vec<stipple::Vertex> = QUAD
-            .iter()
-            .copied()
-            .map(stipple::Vertex::new_with_position)
-            .collect();
+    // nothing covers the paper, so the color is meaningless
+    if alpha < 1.0 / 1024.0 {
+        texel.copy_from_slice(&[0.0; 4]);
+        return;
+    }
 
-        let mut tess: Tess = TessBuilder::new(context)
-            .add_vertices(&stipple_quad)
-            .add_instances(null_instances)
-            .set_mode(Mode::TriangleStrip)
-            .build()
-            .unwrap();
-
-        let aspect = target_buffer.width() as f32 / target_buffer.height() as f32;
-        context.pipeline_builder().pipeline(
-            &target_buffer,
-            [1.0, 1.0, 1.0, 0.0],
-            |pipeline, mut shd_gate| {
-                for layer in layers {
-                    for stipples in layer.stipples() {
-                        let mut instances: Vec<stipple::VertexInstance> =
-                            stipples.instances().map(|stipple| stipple.into()).collect();
-
-                        let bound_mask = pipeline.bind_texture(&stipples.mask.mask);
-                        let bound_colormap = pipeline.bind_texture(&layer.colormap.texture);
-                        let bound_texture =
-                            stipples.texture.map(|e| pipeline.bind_texture(&e.texture));
-
-                        let program = if bound_texture.is_some() {
-                            &stipple_texture_program
-                        } else {
-                            &stipple_program
-                        };
-
-                        shd_gate.shade(program, |iface, mut rdr_gate| {
-                            let render_state = RenderState::default()
-                                .set_blending((Additive, One, SrcAlphaComplement))
-                                .set_depth_test(DepthComparison::Always);
-
-                            rdr_gate.render(render_state, |mut tess_gate| {
-                                if instances.len() > 0 {
-                                    iface.aspect_ratio.update(aspect);
-                                    iface.mask.update(&bound_mask);
-                                    iface.colormap.update(&bound_colormap);
-                                    iface.discard_threshold.update(0.0f32);
-
-                                    if let Some(tex) = bound_texture {
-                                        iface.texture.update(&tex);
-                                    }
-
-                                    for chunk in instances.chunks_mut(INSTANCE_CHUNK_SIZE) {
-                                        tess.as_inst_slice_mut()
-                                            .expect("Must be able to index instances")
-                                            [0..chunk.len()]
-                                            .swap_with_slice(chunk);
-
-                                        if chunk.len() == INSTANCE_CHUNK_SIZE {
-                                            tess_gate.render(&tess);
-                                        } else {
-                                            let slice = TessSlice::inst_whole(&tess, chunk.len());
-                                            tess_gate.render(slice);
-                                        }
-                                    }
-                                }
-                            });
-                        });
-                    }
-                }
-            },
-        );
+    for channel in texel[..3].iter_mut() {
+        *channel = ((*channel - 1.0 + alpha) / alpha).clamp(0.0, 1.0);
     }
 }
@@ -0,0 +1,234 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::backend::DaliBackend;
+use crate::error::DaliError;
+use crate::render::gate_canvas::CanvasGate;
+use crate::render::stats::RenderStats;
+
+/// Receives progress reports during a render.  Set it with [DaliPipeline::set_progress_callback](crate::DaliPipeline::set_progress_callback)
+pub type ProgressCallback = dyn FnMut(&RenderProgress);
+
+/// The progress of a render, which is reported after each chunk of stipples, and each layer.
+///
+/// Layers are counted once per render, on every path.  Tiled renders draw every layer once per tile,
+/// so a layer is done once it has been drawn on the last tile, and stipples are counted once per tile.
+/// Streamed canvases are generated as they are drawn, so their totals are 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderProgress {
+    /// The number of layers which have been drawn, including isolated layers
+    pub layers_done: usize,
+    /// The number of layers in the render
+    pub layers_total: usize,
+    /// The number of stipple instances which have been drawn, on every tile
+    pub stipples_drawn: usize,
+    /// The number of stipple instances in the render, multiplied by the number of tiles
+    pub stipples_total: usize,
+    /// The time since the render started
+    pub elapsed: Duration,
+}
+
+/// Cancels a render, from another thread or from the progress callback.
+/// Set it with [DaliPipeline::set_cancel_token](crate::DaliPipeline::set_cancel_token)
+///
+/// The token is checked between chunks of stipples, and a cancelled render returns [DaliError::Cancelled].
+/// Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancels the current render, and every following render, until the token is reset
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Allows renders to run again, after a cancellation
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// The pipeline's progress callback, cancel token and stats setting, and the results of the last render
+#[derive(Default)]
+pub(crate) struct Tracking {
+    pub(crate) callback: Option<Box<ProgressCallback>>,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) collect_stats: bool,
+    // the number of stipple batches drawn by the last render
+    pub(crate) batch_count: usize,
+    pub(crate) stats: Option<RenderStats>,
+}
+
+impl Tracking {
+    /// Runs a render with a tracker, which reports progress and checks for cancellation.
+    /// The progress callback is lent to the tracker, and the batch count and stats are kept for the pipeline.
+    pub(crate) fn track<T, F>(&mut self, render: F) -> Result<T, DaliError>
+    where
+        F: FnOnce(&mut RenderTracker) -> Result<T, DaliError>,
+    {
+        let stats = if self.collect_stats {
+            Some(RenderStats::default())
+        } else {
+            None
+        };
+
+        let mut tracker = RenderTracker {
+            callback: self.callback.take(),
+            cancel: self.cancel.clone(),
+            start: Instant::now(),
+            progress: RenderProgress::default(),
+            tile: 0,
+            tiles: 1,
+            batches: 0,
+            stats,
+        };
+
+        let result = render(&mut tracker);
+        self.callback = tracker.callback;
+        self.batch_count = tracker.batches;
+        self.stats = tracker.stats;
+        result
+    }
+
+    /// Adds the time spent converting texels into images, after the render
+    pub(crate) fn add_conversion_time(&mut self, elapsed: Duration) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.conversion_time += elapsed;
+        }
+    }
+}
+
+/// Counts the batches and stipples drawn by a render, reports progress, checks for cancellation,
+/// and collects stats (if enabled)
+pub(crate) struct RenderTracker {
+    callback: Option<Box<ProgressCallback>>,
+    cancel: Option<CancelToken>,
+    start: Instant,
+    progress: RenderProgress,
+    // the tile being drawn, and the number of tiles, so layers and batches are only counted once
+    tile: usize,
+    tiles: usize,
+    batches: usize,
+    stats: Option<RenderStats>,
+}
+
+impl RenderTracker {
+    /// Adds the layers of a canvas to the totals, and its stipples once for each tile
+    pub(crate) fn add_totals<B: DaliBackend>(&mut self, canvas: &CanvasGate<B>, tiles: usize) {
+        self.tiles = tiles;
+        for layer in canvas.layers() {
+            let stipples: usize = layer.stipples().map(|gate| gate.instances().len()).sum();
+            self.progress.layers_total += 1;
+            self.progress.stipples_total += stipples * tiles;
+
+            if let Some(stats) = self.stats.as_mut() {
+                stats.layers += 1;
+                stats.stipple_gates += layer.stipples().count();
+                stats.instances += stipples;
+            }
+        }
+    }
+
+    /// Starts drawing a tile, by its index in the render
+    pub(crate) fn start_tile(&mut self, tile: usize) {
+        self.tile = tile;
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        match &self.cancel {
+            Some(cancel) => cancel.is_cancelled(),
+            None => false,
+        }
+    }
+
+    /// Returns DaliError::Cancelled if the render has been cancelled
+    pub(crate) fn check_cancelled(&self) -> Result<(), DaliError> {
+        if self.is_cancelled() {
+            return Err(DaliError::Cancelled);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn stipples_drawn(&mut self, stipples: usize) {
+        self.progress.stipples_drawn += stipples;
+        self.report();
+    }
+
+    /// Counts a layer, once it has been drawn on the last tile
+    pub(crate) fn layer_done(&mut self) {
+        if self.tile + 1 >= self.tiles {
+            self.progress.layers_done += 1;
+        }
+        self.report();
+    }
+
+    /// Counts a stipple batch, once it has been drawn on the first tile
+    pub(crate) fn batch_done(&mut self) {
+        if self.tile == 0 {
+            self.batches += 1;
+        }
+    }
+
+    /// Counts a stipple draw call, which binds a mask and colormap, and an optional texture and clip
+    pub(crate) fn stipple_call(&mut self, texture: bool, clip: bool) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.draw_calls += 1;
+            stats.texture_binds += 2 + texture as usize + clip as usize;
+        }
+    }
+
+    /// Counts a composite draw call, which binds an isolated layer
+    pub(crate) fn composite_call(&mut self) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.draw_calls += 1;
+            stats.texture_binds += 1;
+        }
+    }
+
+    pub(crate) fn add_layer_time(&mut self, index: usize, elapsed: Duration) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.add_layer_time(index, elapsed);
+        }
+    }
+
+    pub(crate) fn add_readback_time(&mut self, elapsed: Duration) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.readback_time += elapsed;
+        }
+    }
+
+    /// Counts a streamed stipple gate, once its stipples have been drawn
+    pub(crate) fn add_streamed_gate(&mut self, instances: usize) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.stipple_gates += 1;
+            stats.instances += instances;
+        }
+    }
+
+    /// Counts a streamed layer, once its stipples have been drawn
+    pub(crate) fn add_streamed_layer(&mut self, elapsed: Duration) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.layers += 1;
+            stats.layer_times.push(elapsed);
+        }
+        self.layer_done();
+    }
+
+    fn report(&mut self) {
+        if let Some(callback) = self.callback.as_mut() {
+            self.progress.elapsed = self.start.elapsed();
+            callback(&self.progress);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::backend::{DaliBackend, GlBackend};
+use crate::render::gate_canvas::CanvasGate;
+use crate::WindowSurface;
+
+/// An owned composition of layers and stipple batches, which can be rendered many times, at any size.
+///
+/// A scene borrows its handles, but not the pipeline, so the same composition can be previewed,
+/// rendered at print size, and inspected or modified between renders via [canvas_mut](Scene::canvas_mut).
+pub struct Scene<'a, B: DaliBackend = GlBackend<WindowSurface>> {
+    canvas: CanvasGate<'a, B>,
+}
+
+impl<'a, B: DaliBackend> Scene<'a, B> {
+    /// Creates an empty scene, which renders as a blank canvas
+    pub fn new() -> Scene<'a, B> {
+        Scene {
+            canvas: CanvasGate::new(),
+        }
+    }
+
+    /// Builds a scene with a generator callback, in the same way as [render_canvas](crate::DaliPipeline::render_canvas)
+    pub fn from_fn<F>(callback: F) -> Scene<'a, B>
+    where
+        F: FnOnce(&mut CanvasGate<'a, B>),
+    {
+        let mut scene = Scene::new();
+        callback(&mut scene.canvas);
+        scene
+    }
+
+    /// The background and layers of the scene
+    pub fn canvas(&self) -> &CanvasGate<'a, B> {
+        &self.canvas
+    }
+
+    /// The background and layers of the scene, which can be extended or modified before the next render
+    pub fn canvas_mut(&mut self) -> &mut CanvasGate<'a, B> {
+        &mut self.canvas
+    }
+}
+
+impl<'a, B: DaliBackend> Default for Scene<'a, B> {
+    fn default() -> Self {
+        Scene::new()
+    }
+}
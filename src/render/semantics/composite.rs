@@ -0,0 +1,52 @@
+use luminance::pipeline::BoundTexture;
+use luminance::pixel::Floating;
+use luminance::shader::program::{Program, ProgramError, Uniform};
+use luminance::texture::{Dim2, Flat};
+use luminance_derive::{Semantics, UniformInterface, Vertex};
+
+const COMPOSITE_VS: &str = include_str!("../../shaders/composite-vs.glsl");
+const COMPOSITE_FS: &str = include_str!("../../shaders/composite-fs.glsl");
+
+pub fn compile() -> Result<Program<CompositeSemantics, (), CompositeInterface>, ProgramError> {
+    let composite_program = Program::<CompositeSemantics, (), CompositeInterface>::from_strings(
+        None,
+        COMPOSITE_VS,
+        None,
+        COMPOSITE_FS,
+    )?;
+
+    Ok(composite_program.ignore_warnings())
+}
+
+#[derive(UniformInterface)]
+pub struct CompositeInterface {
+    #[uniform(unbound, name = "source_layer")]
+    pub layer: Uniform<&'static BoundTexture<'static, Flat, Dim2, Floating>>,
+    #[uniform(unbound, name = "opacity")]
+    pub opacity: Uniform<f32>,
+    #[uniform(unbound, name = "blend_output")]
+    pub blend_output: Uniform<i32>,
+    #[uniform(unbound, name = "source_backdrop")]
+    pub backdrop: Uniform<&'static BoundTexture<'static, Flat, Dim2, Floating>>,
+}
+
+/// A full-screen quad
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Semantics)]
+pub enum CompositeSemantics {
+    #[sem(name = "position", repr = "[f32; 2]", wrapper = "VertexPosition")]
+    Position,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Vertex)]
+#[vertex(sem = "CompositeSemantics")]
+pub struct Vertex {
+    pub position: VertexPosition,
+}
+
+impl Vertex {
+    pub fn new_with_position(position: [f32; 2]) -> Vertex {
+        Vertex {
+            position: VertexPosition::new(position),
+        }
+    }
+}
@@ -1 +1,5 @@
-pub(in crate::render) mod stipple;
\ No newline at end of file
+pub(crate) mod composite;
+
+// luminance-derive generates a constructor with one argument per instance attribute
+#[allow(clippy::too_many_arguments)]
+pub(crate) mod stipple;
@@ -2,37 +2,43 @@ use std::borrow::Borrow;
 
 use luminance::pipeline::BoundTexture;
 use luminance::pixel::Floating;
-use luminance::shader::program::{Program, Uniform};
+use luminance::shader::program::{Program, ProgramError, Uniform};
 use luminance::texture::{Dim2, Flat};
 use luminance_derive::{Semantics, UniformInterface, Vertex};
 
 use crate::stipple::Stipple;
 
-const STIPPLE_VS: &'static str = include_str!("../../shaders/stipple-vs.glsl");
-const STIPPLE_FS: &'static str = include_str!("../../shaders/stipple-fs.glsl");
-const STIPPLE_TEXTURE_FS: &'static str = include_str!("../../shaders/stipple-texture-fs.glsl");
-
-pub fn compile() -> Program<StippleSemantics, (), StippleInterface> {
+const STIPPLE_VS: &str = include_str!("../../shaders/stipple-vs.glsl");
+// the blend function is shared by both fragment shaders
+const STIPPLE_FS: &str = concat!(
+    include_str!("../../shaders/stipple-output.glsl"),
+    include_str!("../../shaders/stipple-fs.glsl")
+);
+const STIPPLE_TEXTURE_FS: &str = concat!(
+    include_str!("../../shaders/stipple-output.glsl"),
+    include_str!("../../shaders/stipple-texture-fs.glsl")
+);
+
+pub fn compile() -> Result<Program<StippleSemantics, (), StippleInterface>, ProgramError> {
     // TODO: figure out how to deal with warnings.  panic?
     let stipple_program = Program::<StippleSemantics, (), StippleInterface>::from_strings(
         None, STIPPLE_VS, None, STIPPLE_FS,
-    )
-    .expect("program creation");
+    )?;
 
-    stipple_program.ignore_warnings()
+    Ok(stipple_program.ignore_warnings())
 }
 
-pub fn compile_with_texture() -> Program<StippleSemantics, (), StippleInterface> {
+pub fn compile_with_texture(
+) -> Result<Program<StippleSemantics, (), StippleInterface>, ProgramError> {
     // TODO: figure out how to deal with warnings.  panic?
     let stipple_program = Program::<StippleSemantics, (), StippleInterface>::from_strings(
         None,
         STIPPLE_VS,
         None,
         STIPPLE_TEXTURE_FS,
-    )
-    .expect("program creation");
+    )?;
 
-    stipple_program.ignore_warnings()
+    Ok(stipple_program.ignore_warnings())
 }
 
 #[derive(UniformInterface)]
@@ -46,8 +52,18 @@ pub struct StippleInterface {
     pub colormap: Uniform<&'static BoundTexture<'static, Flat, Dim2, Floating>>,
     #[uniform(unbound, name = "aspect_ratio")]
     pub aspect_ratio: Uniform<f32>,
+    #[uniform(unbound, name = "tile_transform")]
+    pub tile_transform: Uniform<[f32; 4]>,
     #[uniform(unbound, name = "discard_threshold")]
     pub discard_threshold: Uniform<f32>,
+    #[uniform(unbound, name = "blend_output")]
+    pub blend_output: Uniform<i32>,
+    #[uniform(unbound, name = "source_clip")]
+    pub clip: Uniform<&'static BoundTexture<'static, Flat, Dim2, Floating>>,
+    #[uniform(unbound, name = "clip_enabled")]
+    pub clip_enabled: Uniform<bool>,
+    #[uniform(unbound, name = "source_backdrop")]
+    pub backdrop: Uniform<&'static BoundTexture<'static, Flat, Dim2, Floating>>,
 }
 
 /// See Stipple for more details on representation and variable effects.
@@ -56,6 +72,20 @@ pub enum StippleSemantics {
     #[sem(name = "position", repr = "[f32; 2]", wrapper = "VertexPosition")]
     Position,
 
+    #[sem(
+        name = "transform",
+        repr = "[f32; 4]",
+        wrapper = "VertexInstanceTransform"
+    )]
+    InstanceTransform,
+
+    #[sem(
+        name = "transform_offset",
+        repr = "[f32; 2]",
+        wrapper = "VertexInstanceTransformOffset"
+    )]
+    InstanceTransformOffset,
+
     #[sem(
         name = "translation",
         repr = "[f32; 2]",
@@ -74,6 +104,20 @@ pub enum StippleSemantics {
     )]
     InstanceColormapScale,
 
+    #[sem(
+        name = "colormap_translation",
+        repr = "[f32; 2]",
+        wrapper = "VertexInstanceColormapTranslation"
+    )]
+    InstanceColormapTranslation,
+
+    #[sem(
+        name = "colormap_rotation",
+        repr = "f32",
+        wrapper = "VertexInstanceColormapRotation"
+    )]
+    InstanceColormapRotation,
+
     #[sem(name = "rotation", repr = "f32", wrapper = "VertexInstanceRotation")]
     InstanceRotation,
 
@@ -86,6 +130,12 @@ pub enum StippleSemantics {
         wrapper = "VertexInstanceTextureRotation"
     )]
     InstanceTextureRotation,
+
+    #[sem(name = "tint", repr = "[f32; 4]", wrapper = "VertexInstanceTint")]
+    InstanceTint,
+
+    #[sem(name = "opacity", repr = "f32", wrapper = "VertexInstanceOpacity")]
+    InstanceOpacity,
 }
 
 #[repr(C)]
@@ -107,24 +157,39 @@ impl Vertex {
 #[derive(Clone, Copy, Debug, PartialEq, Vertex)]
 #[vertex(sem = "StippleSemantics", instanced = "true")]
 pub struct VertexInstance {
+    pub transform: VertexInstanceTransform,
+    pub transform_offset: VertexInstanceTransformOffset,
     pub translation: VertexInstanceTranslation,
     pub scale: VertexInstanceScale,
     pub colormap_scale: VertexInstanceColormapScale,
+    pub colormap_translation: VertexInstanceColormapTranslation,
+    pub colormap_rotation: VertexInstanceColormapRotation,
     pub rotation: VertexInstanceRotation,
     pub texture_rotation: VertexInstanceTextureRotation,
     pub gamma: VertexInstanceGamma,
+    pub tint: VertexInstanceTint,
+    pub opacity: VertexInstanceOpacity,
 }
 
 impl<T: Borrow<Stipple>> From<T> for VertexInstance {
     fn from(stipple: T) -> Self {
         let stipple = stipple.borrow();
+        let [row_x, row_y] = stipple.transform;
         VertexInstance {
+            transform: VertexInstanceTransform::new([row_x[0], row_x[1], row_y[0], row_y[1]]),
+            transform_offset: VertexInstanceTransformOffset::new([row_x[2], row_y[2]]),
             translation: VertexInstanceTranslation::new(stipple.translation),
             scale: VertexInstanceScale::new(stipple.scale),
             colormap_scale: VertexInstanceColormapScale::new(stipple.colormap_scale),
+            colormap_translation: VertexInstanceColormapTranslation::new(
+                stipple.colormap_translation,
+            ),
+            colormap_rotation: VertexInstanceColormapRotation::new(stipple.colormap_rotation),
             rotation: VertexInstanceRotation::new(stipple.rotation),
             texture_rotation: VertexInstanceTextureRotation::new(stipple.texture_rotation),
             gamma: VertexInstanceGamma::new(stipple.gamma),
+            tint: VertexInstanceTint::new(stipple.tint),
+            opacity: VertexInstanceOpacity::new(stipple.opacity),
         }
     }
 }
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+/// Statistics for a render, which show where the time goes.
+/// Enable them with [DaliPipeline::set_collect_stats](crate::DaliPipeline::set_collect_stats)
+///
+/// Tiled renders draw every layer once per tile, so draw calls, texture binds and times are summed over the tiles.
+/// The backend may draw asynchronously (like OpenGL), so layer times measure how long the layer took to submit,
+/// and the GPU may finish the work during the readback.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    /// The number of layers in the canvas
+    pub layers: usize,
+    /// The number of stipple gates in the canvas, including empty gates
+    pub stipple_gates: usize,
+    /// The number of stipple instances in the canvas
+    pub instances: usize,
+    /// The number of draw calls submitted to the backend, including backgrounds and isolated layer composites
+    pub draw_calls: usize,
+    /// The number of textures bound by the draw calls
+    pub texture_binds: usize,
+    /// The time spent drawing each layer, in layer order.  Isolated layers include their composite.
+    pub layer_times: Vec<Duration>,
+    /// The time spent reading rendered texels back from the backend
+    pub readback_time: Duration,
+    /// The time spent converting texels into images
+    pub conversion_time: Duration,
+}
+
+impl RenderStats {
+    /// Adds time to a layer, and extends the layer times if the layer is new
+    pub(crate) fn add_layer_time(&mut self, index: usize, elapsed: Duration) {
+        if self.layer_times.len() <= index {
+            self.layer_times.resize(index + 1, Duration::default());
+        }
+        self.layer_times[index] += elapsed;
+    }
+}
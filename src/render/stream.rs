@@ -0,0 +1,201 @@
+use std::time::Instant;
+
+use crate::backend::{BackendFrame, DaliBackend, GlBackend, INSTANCE_CHUNK_SIZE};
+use crate::blend::BlendMode;
+use crate::clip::ClipHandle;
+use crate::colormap::ColormapHandle;
+use crate::error::DaliError;
+use crate::render::progress::RenderTracker;
+use crate::stipple::Stipple;
+use crate::texture::TextureHandle;
+use crate::{MaskHandle, WindowSurface};
+
+/// Draws layers as they are generated, during [stream_canvas](crate::DaliPipeline::stream_canvas).
+///
+/// Unlike [CanvasGate](crate::CanvasGate), stipples are flushed to the backend in chunks of instances as they are drawn,
+/// so memory use is constant, regardless of the number of stipples.
+/// Layers are painted directly onto the canvas, so layer opacity and layer blend modes are not available.
+pub struct CanvasStream<'f, B: DaliBackend = GlBackend<WindowSurface>> {
+    frame: &'f mut dyn BackendFrame<B>,
+    // the first error (or cancellation) stops the render, and is returned by stream_canvas
+    result: Result<(), DaliError>,
+    tracker: &'f mut RenderTracker,
+}
+
+impl<'f, B: DaliBackend> CanvasStream<'f, B> {
+    pub(crate) fn new(
+        frame: &'f mut dyn BackendFrame<B>,
+        tracker: &'f mut RenderTracker,
+    ) -> CanvasStream<'f, B> {
+        CanvasStream {
+            frame,
+            result: Ok(()),
+            tracker,
+        }
+    }
+
+    pub fn layer<F>(&mut self, colormap: &ColormapHandle<B>, callback: F)
+    where
+        F: FnOnce(&mut LayerStream<'_, 'f, B>),
+    {
+        let start = Instant::now();
+        let mut layer = LayerStream {
+            canvas: self,
+            colormap,
+            clip: None,
+        };
+        callback(&mut layer);
+        self.tracker.add_streamed_layer(start.elapsed());
+    }
+
+    /// Adds a layer which only paints where the clip mask is white.
+    /// The clip is stretched over the whole canvas.
+    pub fn layer_with_clip<F>(
+        &mut self,
+        colormap: &ColormapHandle<B>,
+        clip: &ClipHandle<B>,
+        callback: F,
+    ) where
+        F: FnOnce(&mut LayerStream<'_, 'f, B>),
+    {
+        let start = Instant::now();
+        let mut layer = LayerStream {
+            canvas: self,
+            colormap,
+            clip: Some(clip),
+        };
+        callback(&mut layer);
+        self.tracker.add_streamed_layer(start.elapsed());
+    }
+
+    /// Returns the first error, or DaliError::Cancelled if the generator stopped early because of a cancellation
+    pub(crate) fn finish(self) -> Result<(), DaliError> {
+        self.result?;
+        self.tracker.check_cancelled()
+    }
+}
+
+/// Draws stipple batches into a streamed layer
+pub struct LayerStream<'c, 'f, B: DaliBackend = GlBackend<WindowSurface>> {
+    canvas: &'c mut CanvasStream<'f, B>,
+    colormap: &'c ColormapHandle<B>,
+    clip: Option<&'c ClipHandle<B>>,
+}
+
+impl<'c, 'f, B: DaliBackend> LayerStream<'c, 'f, B> {
+    pub fn stipple<F>(&mut self, mask: &MaskHandle<B>, callback: F)
+    where
+        F: FnOnce(&mut StippleStream<B>),
+    {
+        self.stream(mask, None, callback);
+    }
+
+    pub fn stipple_with_texture<F>(
+        &mut self,
+        mask: &MaskHandle<B>,
+        texture: &TextureHandle<B>,
+        callback: F,
+    ) where
+        F: FnOnce(&mut StippleStream<B>),
+    {
+        self.stream(mask, Some(texture), callback);
+    }
+
+    fn stream<F>(&mut self, mask: &MaskHandle<B>, texture: Option<&TextureHandle<B>>, callback: F)
+    where
+        F: FnOnce(&mut StippleStream<B>),
+    {
+        let mut stipples = StippleStream {
+            frame: &mut *self.canvas.frame,
+            result: &mut self.canvas.result,
+            tracker: &mut *self.canvas.tracker,
+            colormap: self.colormap,
+            mask,
+            texture,
+            clip: self.clip,
+            blend_mode: BlendMode::default(),
+            stipples: Vec::with_capacity(INSTANCE_CHUNK_SIZE),
+            count: 0,
+            batched: false,
+        };
+
+        callback(&mut stipples);
+        stipples.flush();
+        stipples.tracker.add_streamed_gate(stipples.count);
+    }
+}
+
+/// Collects stipples from the user, and flushes them to the backend each time a chunk of instances is full.
+///
+/// Generators should check [is_cancelled](StippleStream::is_cancelled) while they run, and stop early,
+/// because stipples drawn after a cancellation are discarded.
+pub struct StippleStream<'s, B: DaliBackend = GlBackend<WindowSurface>> {
+    frame: &'s mut dyn BackendFrame<B>,
+    result: &'s mut Result<(), DaliError>,
+    tracker: &'s mut RenderTracker,
+    colormap: &'s ColormapHandle<B>,
+    mask: &'s MaskHandle<B>,
+    texture: Option<&'s TextureHandle<B>>,
+    clip: Option<&'s ClipHandle<B>>,
+    blend_mode: BlendMode,
+    stipples: Vec<Stipple>,
+    // the number of stipples drawn into this gate
+    count: usize,
+    // true once a chunk has been drawn with the current blend mode, which is counted as one batch
+    batched: bool,
+}
+
+impl<'s, B: DaliBackend> StippleStream<'s, B> {
+    /// Sets the blend mode for the following stipples in this batch
+    /// Default: BlendMode::Normal
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        // stipples which were already drawn keep the previous blend mode
+        self.flush();
+        if self.blend_mode != blend_mode {
+            self.batched = false;
+        }
+        self.blend_mode = blend_mode;
+    }
+
+    /// Returns true if the render has been cancelled (or has failed), so the generator can stop drawing
+    pub fn is_cancelled(&self) -> bool {
+        self.result.is_err() || self.tracker.is_cancelled()
+    }
+
+    pub fn draw(&mut self, stipple: Stipple) {
+        self.stipples.push(stipple);
+        self.count += 1;
+        if self.stipples.len() >= INSTANCE_CHUNK_SIZE {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.stipples.is_empty() && self.result.is_ok() {
+            *self.result = self.draw_chunk();
+        }
+
+        self.stipples.clear();
+    }
+
+    /// Draws the collected stipples, unless the render has been cancelled
+    fn draw_chunk(&mut self) -> Result<(), DaliError> {
+        self.tracker.check_cancelled()?;
+        self.frame.draw_stipples(
+            &self.colormap.texture,
+            &self.mask.mask,
+            self.texture.map(|texture| &texture.texture),
+            self.clip.map(|clip| &clip.clip),
+            self.blend_mode,
+            &self.stipples,
+        )?;
+        if !self.batched {
+            self.batched = true;
+            self.tracker.batch_done();
+        }
+        self.tracker
+            .stipple_call(self.texture.is_some(), self.clip.is_some());
+        self.tracker.stipples_drawn(self.stipples.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,716 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::DaliBackend;
+use crate::background::Background;
+use crate::blend::BlendMode;
+use crate::clip::ClipHandle;
+use crate::colormap::ColormapHandle;
+use crate::error::DaliError;
+use crate::mask::MaskFit;
+use crate::render::gate_layer::LayerGate;
+use crate::render::gate_stipple::StippleGate;
+use crate::render::scene::Scene;
+use crate::texture::TextureHandle;
+use crate::{MaskHandle, Stipple};
+
+/// The version written to new scene files.  Files with a newer version are rejected.
+/// Version 2 records how masks and textures are fit.  Version 1 files are read with MaskFit::Crop.
+/// Version 3 records order independent layers.  Older files are read with ordered layers.
+pub const SCENE_FILE_VERSION: u32 = 3;
+
+/// Binary scene files start with this header, followed by the bincode-encoded scene
+const BINARY_MAGIC: &[u8; 8] = b"DALISCN\0";
+
+/// The encoding of a `.dali` scene file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SceneFormat {
+    /// Pretty-printed JSON, which is readable and diffable
+    Json,
+    /// A compact binary encoding, for scenes with millions of stipples
+    Binary,
+}
+
+/// A serializable record of a [Scene]: every layer, stipple batch and stipple instance, and the asset paths they reference.
+///
+/// Record a scene with [SceneFile::record], and save it as a `.dali` file.  To replay it (e.g. at a higher resolution),
+/// load the file, load its assets with [DaliPipeline::load_scene_assets](crate::DaliPipeline::load_scene_assets),
+/// and build the scene with [SceneAssets::scene].
+///
+/// Assets are referenced by path, so they must be loaded with the pipeline's `*_from_file` methods before recording.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    /// Each asset file used by the scene, in the order it was first used
+    pub assets: Vec<AssetRecord>,
+    pub background: BackgroundRecord,
+    pub layers: Vec<LayerRecord>,
+}
+
+/// An image file used by a scene
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AssetRecord {
+    pub path: PathBuf,
+    pub kind: AssetKind,
+}
+
+/// How an asset file is loaded
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AssetKind {
+    Colormap,
+    Mask {
+        mipmaps: usize,
+        #[serde(default = "recorded_fit")]
+        fit: MaskFit,
+    },
+    Texture {
+        mipmaps: usize,
+        #[serde(default = "recorded_fit")]
+        fit: MaskFit,
+    },
+    Clip,
+}
+
+/// The fit of masks and textures in version 1 files, which cropped every mask and texture
+fn recorded_fit() -> MaskFit {
+    MaskFit::Crop
+}
+
+/// The layout of version 1 scene files.
+/// Bincode is positional, so it can't fill in missing fields with serde defaults like JSON does.
+#[derive(Deserialize)]
+struct SceneFileV1 {
+    assets: Vec<AssetRecordV1>,
+    background: BackgroundRecord,
+    layers: Vec<LayerRecordV2>,
+}
+
+/// The layout of version 2 scene files
+#[derive(Deserialize)]
+struct SceneFileV2 {
+    assets: Vec<AssetRecord>,
+    background: BackgroundRecord,
+    layers: Vec<LayerRecordV2>,
+}
+
+/// The layout of layers in version 1 and 2 scene files, which didn't record order_independent
+#[derive(Deserialize)]
+struct LayerRecordV2 {
+    colormap: PathBuf,
+    clip: Option<PathBuf>,
+    opacity: f32,
+    blend_mode: BlendMode,
+    isolated: bool,
+    stipples: Vec<StippleBatchRecord>,
+}
+
+#[derive(Deserialize)]
+struct AssetRecordV1 {
+    path: PathBuf,
+    kind: AssetKindV1,
+}
+
+#[derive(Deserialize)]
+enum AssetKindV1 {
+    Colormap,
+    Mask { mipmaps: usize },
+    Texture { mipmaps: usize },
+    Clip,
+}
+
+impl From<AssetKindV1> for AssetKind {
+    fn from(kind: AssetKindV1) -> AssetKind {
+        match kind {
+            AssetKindV1::Colormap => AssetKind::Colormap,
+            AssetKindV1::Mask { mipmaps } => AssetKind::Mask {
+                mipmaps,
+                fit: recorded_fit(),
+            },
+            AssetKindV1::Texture { mipmaps } => AssetKind::Texture {
+                mipmaps,
+                fit: recorded_fit(),
+            },
+            AssetKindV1::Clip => AssetKind::Clip,
+        }
+    }
+}
+
+impl From<SceneFileV1> for SceneFile {
+    fn from(file: SceneFileV1) -> SceneFile {
+        let assets = file
+            .assets
+            .into_iter()
+            .map(|asset| AssetRecord {
+                path: asset.path,
+                kind: asset.kind.into(),
+            })
+            .collect();
+
+        SceneFile::from(SceneFileV2 {
+            assets,
+            background: file.background,
+            layers: file.layers,
+        })
+    }
+}
+
+impl From<SceneFileV2> for SceneFile {
+    fn from(file: SceneFileV2) -> SceneFile {
+        SceneFile {
+            version: SCENE_FILE_VERSION,
+            assets: file.assets,
+            background: file.background,
+            layers: file.layers.into_iter().map(LayerRecord::from).collect(),
+        }
+    }
+}
+
+impl From<LayerRecordV2> for LayerRecord {
+    fn from(layer: LayerRecordV2) -> LayerRecord {
+        LayerRecord {
+            colormap: layer.colormap,
+            clip: layer.clip,
+            opacity: layer.opacity,
+            blend_mode: layer.blend_mode,
+            isolated: layer.isolated,
+            order_independent: false,
+            stipples: layer.stipples,
+        }
+    }
+}
+
+/// The starting canvas.  Image backgrounds are not recorded, as they have no file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BackgroundRecord {
+    Color([f32; 4]),
+    Colormap(PathBuf),
+}
+
+/// A layer, and the stipple batches drawn in it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayerRecord {
+    pub colormap: PathBuf,
+    pub clip: Option<PathBuf>,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub isolated: bool,
+    #[serde(default)]
+    pub order_independent: bool,
+    pub stipples: Vec<StippleBatchRecord>,
+}
+
+/// A stipple gate, and all of its stipple instances
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StippleBatchRecord {
+    pub mask: PathBuf,
+    pub texture: Option<PathBuf>,
+    pub blend_mode: BlendMode,
+    pub instances: Vec<Stipple>,
+}
+
+impl SceneFile {
+    /// Records a scene.
+    /// Returns an error if the scene uses a handle that wasn't loaded from a file, or an image background
+    pub fn record<B: DaliBackend>(scene: &Scene<B>) -> Result<SceneFile, DaliError> {
+        let mut recorder = Recorder { assets: Vec::new() };
+        let canvas = scene.canvas();
+
+        let background = match canvas.background() {
+            Background::Color(color) => BackgroundRecord::Color(*color),
+            Background::Colormap(colormap) => BackgroundRecord::Colormap(recorder.asset(
+                "colormap",
+                colormap.path(),
+                AssetKind::Colormap,
+            )?),
+            Background::Image(_) => {
+                return Err(DaliError::SceneFile(
+                    "image backgrounds can't be recorded, use a colormap loaded from a file"
+                        .to_string(),
+                ))
+            }
+        };
+
+        let mut layers = Vec::new();
+        for layer in canvas.layers() {
+            let colormap =
+                recorder.asset("colormap", layer.colormap.path(), AssetKind::Colormap)?;
+            let clip = match layer.clip {
+                Some(clip) => Some(recorder.asset("clip", clip.path(), AssetKind::Clip)?),
+                None => None,
+            };
+
+            let mut stipples = Vec::new();
+            for batch in layer.stipples() {
+                let mask = recorder.asset(
+                    "mask",
+                    batch.mask.path(),
+                    AssetKind::Mask {
+                        mipmaps: batch.mask.mipmaps,
+                        fit: batch.mask.fit,
+                    },
+                )?;
+
+                let texture = match batch.texture {
+                    Some(texture) => Some(recorder.asset(
+                        "texture",
+                        texture.path(),
+                        AssetKind::Texture {
+                            mipmaps: texture.mipmaps,
+                            fit: texture.fit,
+                        },
+                    )?),
+                    None => None,
+                };
+
+                stipples.push(StippleBatchRecord {
+                    mask,
+                    texture,
+                    blend_mode: batch.blend_mode,
+                    instances: batch.instances().to_vec(),
+                });
+            }
+
+            layers.push(LayerRecord {
+                colormap,
+                clip,
+                opacity: layer.opacity,
+                blend_mode: layer.blend_mode,
+                isolated: layer.isolated,
+                order_independent: layer.order_independent,
+                stipples,
+            });
+        }
+
+        Ok(SceneFile {
+            version: SCENE_FILE_VERSION,
+            assets: recorder.assets,
+            background,
+            layers,
+        })
+    }
+
+    /// Encodes the scene as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String, DaliError> {
+        serde_json::to_string_pretty(self).map_err(|e| DaliError::SceneFile(e.to_string()))
+    }
+
+    /// Decodes a JSON scene.  Older files are upgraded to [SCENE_FILE_VERSION]
+    pub fn from_json(json: &str) -> Result<SceneFile, DaliError> {
+        let mut file: SceneFile =
+            serde_json::from_str(json).map_err(|e| DaliError::SceneFile(e.to_string()))?;
+        check_version(file.version)?;
+
+        // missing fields were filled in with their serde defaults
+        file.version = SCENE_FILE_VERSION;
+        Ok(file)
+    }
+
+    /// Encodes the scene in the compact binary format
+    pub fn to_binary(&self) -> Result<Vec<u8>, DaliError> {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, self)
+            .map_err(|e| DaliError::SceneFile(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    /// Decodes a binary scene.  Older files are upgraded to [SCENE_FILE_VERSION]
+    pub fn from_binary(bytes: &[u8]) -> Result<SceneFile, DaliError> {
+        if !bytes.starts_with(BINARY_MAGIC) {
+            return Err(DaliError::SceneFile("not a binary scene file".to_string()));
+        }
+
+        // the version is the first field, and it selects the layout of the rest of the file
+        let body = &bytes[BINARY_MAGIC.len()..];
+        let version: u32 = decode_binary(body)?;
+        check_version(version)?;
+
+        match version {
+            1 => {
+                let (_version, file): (u32, SceneFileV1) = decode_binary(body)?;
+                Ok(file.into())
+            }
+            2 => {
+                let (_version, file): (u32, SceneFileV2) = decode_binary(body)?;
+                Ok(file.into())
+            }
+            _ => decode_binary(body),
+        }
+    }
+
+    /// Writes the scene to a file, in the given format
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: SceneFormat) -> Result<(), DaliError> {
+        let bytes = match format {
+            SceneFormat::Json => self.to_json()?.into_bytes(),
+            SceneFormat::Binary => self.to_binary()?,
+        };
+
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// Reads a scene file, in either format
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SceneFile, DaliError> {
+        let bytes = fs::read(path)?;
+        if bytes.starts_with(BINARY_MAGIC) {
+            return SceneFile::from_binary(&bytes);
+        }
+
+        let json = String::from_utf8(bytes).map_err(|e| DaliError::SceneFile(e.to_string()))?;
+        SceneFile::from_json(&json)
+    }
+}
+
+fn check_version(version: u32) -> Result<(), DaliError> {
+    if version > SCENE_FILE_VERSION {
+        return Err(DaliError::SceneFile(format!(
+            "scene file version {} is newer than the supported version {}",
+            version, SCENE_FILE_VERSION
+        )));
+    }
+
+    Ok(())
+}
+
+fn decode_binary<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, DaliError> {
+    bincode::deserialize(bytes).map_err(|e| DaliError::SceneFile(e.to_string()))
+}
+
+/// Collects the assets used by a scene, in the order they are first used
+struct Recorder {
+    assets: Vec<AssetRecord>,
+}
+
+impl Recorder {
+    fn asset(
+        &mut self,
+        name: &str,
+        path: Option<&Path>,
+        kind: AssetKind,
+    ) -> Result<PathBuf, DaliError> {
+        let path = path.ok_or_else(|| {
+            DaliError::SceneFile(format!(
+                "a {} wasn't loaded from a file, so it can't be recorded",
+                name
+            ))
+        })?;
+
+        let record = AssetRecord {
+            path: path.to_path_buf(),
+            kind,
+        };
+
+        if !self.assets.contains(&record) {
+            self.assets.push(record);
+        }
+
+        Ok(path.to_path_buf())
+    }
+}
+
+/// The assets referenced by a [SceneFile], keyed by path.  Load them with [DaliPipeline::load_scene_assets](crate::DaliPipeline::load_scene_assets)
+pub struct SceneAssets<B: DaliBackend> {
+    pub(crate) colormaps: HashMap<PathBuf, ColormapHandle<B>>,
+    pub(crate) masks: HashMap<PathBuf, MaskHandle<B>>,
+    pub(crate) textures: HashMap<PathBuf, TextureHandle<B>>,
+    pub(crate) clips: HashMap<PathBuf, ClipHandle<B>>,
+}
+
+impl<B: DaliBackend> SceneAssets<B> {
+    pub(crate) fn new() -> SceneAssets<B> {
+        SceneAssets {
+            colormaps: HashMap::new(),
+            masks: HashMap::new(),
+            textures: HashMap::new(),
+            clips: HashMap::new(),
+        }
+    }
+
+    /// Rebuilds the recorded scene, with these assets.
+    /// Returns an error if the file references an asset that wasn't loaded
+    pub fn scene(&self, file: &SceneFile) -> Result<Scene<'_, B>, DaliError> {
+        let mut scene = Scene::new();
+        let canvas = scene.canvas_mut();
+
+        match &file.background {
+            BackgroundRecord::Color(color) => canvas.set_background(Background::Color(*color)),
+            BackgroundRecord::Colormap(path) => {
+                canvas.set_background(Background::Colormap(lookup(&self.colormaps, path)?))
+            }
+        }
+
+        for record in &file.layers {
+            let colormap = lookup(&self.colormaps, &record.colormap)?;
+            let mut layer = match &record.clip {
+                Some(clip) => LayerGate::new_with_clip(colormap, lookup(&self.clips, clip)?),
+                None => LayerGate::new(colormap),
+            };
+
+            layer.set_opacity(record.opacity);
+            layer.set_blend_mode(record.blend_mode);
+            layer.set_isolated(record.isolated);
+            layer.set_order_independent(record.order_independent);
+
+            for batch in &record.stipples {
+                let mask = lookup(&self.masks, &batch.mask)?;
+                let mut stipples = match &batch.texture {
+                    Some(texture) => {
+                        StippleGate::new_with_texture(mask, lookup(&self.textures, texture)?)
+                    }
+                    None => StippleGate::new(mask),
+                };
+
+                stipples.set_blend_mode(batch.blend_mode);
+                stipples
+                    .instances_mut()
+                    .extend(batch.instances.iter().cloned());
+                layer.push_stipples(stipples);
+            }
+
+            canvas.push_layer(layer);
+        }
+
+        Ok(scene)
+    }
+}
+
+fn lookup<'a, T>(assets: &'a HashMap<PathBuf, T>, path: &Path) -> Result<&'a T, DaliError> {
+    assets
+        .get(path)
+        .ok_or_else(|| DaliError::SceneFile(format!("asset {} was not loaded", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{AssetKind, BackgroundRecord, SceneFile, SceneFormat, SCENE_FILE_VERSION};
+    use crate::error::DaliError;
+    use crate::{Background, BlendMode, DaliPipeline, MaskFit, Scene, SoftwareRenderer, Stipple};
+
+    fn asset_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dali-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn records_and_replays_scene() {
+        let dir = asset_dir("replay");
+        let colormap_path = dir.join("colormap.png");
+        let mask_path = dir.join("mask.png");
+        let texture_path = dir.join("texture.png");
+        let clip_path = dir.join("clip.png");
+
+        image::RgbaImage::from_fn(8, 8, |x, y| {
+            image::Rgba([x as u8 * 30, y as u8 * 30, 128, 255])
+        })
+        .save(&colormap_path)
+        .unwrap();
+        image::GrayImage::from_fn(16, 16, |x, y| image::Luma([((x + y) * 8) as u8]))
+            .save(&mask_path)
+            .unwrap();
+        image::GrayImage::from_fn(16, 16, |x, _y| image::Luma([(x * 16) as u8]))
+            .save(&texture_path)
+            .unwrap();
+        image::GrayImage::from_fn(4, 4, |x, _y| image::Luma([if x < 2 { 255 } else { 64 }]))
+            .save(&clip_path)
+            .unwrap();
+
+        let mut pipeline = DaliPipeline::new(SoftwareRenderer::new());
+        let colormap = pipeline.colormap_from_file(&colormap_path);
+        let mask = pipeline.mask_from_file(&mask_path, 2);
+        let texture = pipeline.texture_from_file(&texture_path, 2);
+        let clip = pipeline.clip_from_file(&clip_path);
+
+        let scene = Scene::from_fn(|canvas| {
+            canvas.set_background(Background::Colormap(&colormap));
+            canvas.layer_with_clip(&colormap, &clip, |layer| {
+                layer.set_opacity(0.75);
+                layer.stipple_with_texture(&mask, &texture, |stipples| {
+                    stipples.set_blend_mode(BlendMode::Multiply);
+                    stipples.draw(Stipple::default().with_scale([0.5, 0.5]).with_rotation(0.4));
+                    stipples.draw(Stipple::default().with_translation([0.3, -0.2]));
+                });
+            });
+            canvas.layer(&colormap, |layer| {
+                layer.set_order_independent(true);
+                for translation in [[-0.4, 0.0], [0.4, 0.0]].iter() {
+                    layer.stipple_with_texture(&mask, &texture, |stipples| {
+                        stipples.draw(Stipple::default().with_translation(*translation));
+                    });
+                    layer.stipple(&mask, |stipples| {
+                        stipples.draw(Stipple::default().with_scale([0.3, 0.3]));
+                    });
+                }
+            });
+        });
+
+        let file = SceneFile::record(&scene).unwrap();
+        assert_eq!(4, file.assets.len());
+        let order_independent: Vec<bool> = file
+            .layers
+            .iter()
+            .map(|layer| layer.order_independent)
+            .collect();
+        assert_eq!(vec![false, true], order_independent);
+        assert_eq!(
+            file,
+            SceneFile::from_json(&file.to_json().unwrap()).unwrap()
+        );
+        assert_eq!(
+            file,
+            SceneFile::from_binary(&file.to_binary().unwrap()).unwrap()
+        );
+
+        for (name, format) in [
+            ("scene.dali", SceneFormat::Json),
+            ("scene.bin.dali", SceneFormat::Binary),
+        ]
+        .iter()
+        {
+            let path = dir.join(name);
+            file.save(&path, *format).unwrap();
+            assert_eq!(file, SceneFile::load(&path).unwrap());
+        }
+
+        let mut replay = DaliPipeline::new(SoftwareRenderer::new());
+        let assets = replay.load_scene_assets(&file);
+        let replayed = assets.scene(&file).unwrap();
+        let order_independent: Vec<bool> = replayed
+            .canvas()
+            .layers()
+            .map(|layer| layer.order_independent())
+            .collect();
+        assert_eq!(vec![false, true], order_independent);
+
+        let expected = pipeline.render_scene([32, 24], &scene);
+        let actual = replay.render_scene([32, 24], &replayed);
+        assert_eq!(expected.into_raw(), actual.into_raw());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_assets_without_paths() {
+        let mut pipeline = DaliPipeline::new(SoftwareRenderer::new());
+        let colormap = pipeline.colormap([2, 2], |_x, _y| [1.0; 4]);
+
+        let scene = Scene::from_fn(|canvas| {
+            canvas.layer(&colormap, |_layer| {});
+        });
+
+        match SceneFile::record(&scene) {
+            Err(DaliError::SceneFile(_)) => {}
+            _ => panic!("Expected a scene file error"),
+        }
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let json =
+            r#"{"version": 99, "assets": [], "background": {"Color": [1, 1, 1, 0]}, "layers": []}"#;
+        assert!(SceneFile::from_json(json).is_err());
+
+        let current = json.replace("99", "1");
+        assert!(SceneFile::from_json(&current).is_ok());
+    }
+
+    #[test]
+    fn crops_masks_from_version_1_files() {
+        let json = r#"{
+            "version": 1,
+            "assets": [{"path": "mask.png", "kind": {"Mask": {"mipmaps": 2}}}],
+            "background": {"Color": [1, 1, 1, 0]},
+            "layers": []
+        }"#;
+
+        let file = SceneFile::from_json(json).unwrap();
+        assert_eq!(
+            AssetKind::Mask {
+                mipmaps: 2,
+                fit: MaskFit::Crop
+            },
+            file.assets[0].kind
+        );
+    }
+
+    #[test]
+    fn reads_version_1_binary_files() {
+        let bytes = include_bytes!("../testdata/version-1.bin.dali");
+        let file = SceneFile::from_binary(bytes).unwrap();
+
+        assert_eq!(SCENE_FILE_VERSION, file.version);
+        let kinds: Vec<AssetKind> = file.assets.iter().map(|asset| asset.kind).collect();
+        assert_eq!(
+            vec![
+                AssetKind::Colormap,
+                AssetKind::Mask {
+                    mipmaps: 2,
+                    fit: MaskFit::Crop
+                },
+                AssetKind::Texture {
+                    mipmaps: 1,
+                    fit: MaskFit::Crop
+                },
+                AssetKind::Clip,
+            ],
+            kinds
+        );
+        assert_eq!(
+            BackgroundRecord::Color([1.0, 0.5, 0.25, 1.0]),
+            file.background
+        );
+
+        let layer = &file.layers[0];
+        assert_eq!(Some(PathBuf::from("clip.png")), layer.clip);
+        assert_eq!((0.75, true), (layer.opacity, layer.isolated));
+        assert_eq!(BlendMode::Multiply, layer.stipples[0].blend_mode);
+        assert_eq!(
+            vec![
+                Stipple::default().with_scale([0.5, 0.5]).with_rotation(0.5),
+                Stipple::default().with_translation([0.25, -0.5]),
+            ],
+            layer.stipples[0].instances
+        );
+
+        assert!(!layer.order_independent);
+
+        // upgraded files are written in the current layout
+        assert_eq!(
+            file,
+            SceneFile::from_binary(&file.to_binary().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn reads_version_2_binary_files() {
+        let bytes = include_bytes!("../testdata/version-2.bin.dali");
+        let file = SceneFile::from_binary(bytes).unwrap();
+
+        assert_eq!(SCENE_FILE_VERSION, file.version);
+        assert_eq!(
+            AssetKind::Mask {
+                mipmaps: 2,
+                fit: MaskFit::Pad
+            },
+            file.assets[1].kind
+        );
+        assert_eq!(
+            AssetKind::Texture {
+                mipmaps: 1,
+                fit: MaskFit::Stretch
+            },
+            file.assets[2].kind
+        );
+
+        let layer = &file.layers[0];
+        assert_eq!(
+            (0.75, true, false),
+            (layer.opacity, layer.isolated, layer.order_independent)
+        );
+        assert_eq!(2, layer.stipples[0].instances.len());
+    }
+}
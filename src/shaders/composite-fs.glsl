@@ -0,0 +1,32 @@
+// Composites an isolated layer onto the canvas.
+// Layers follow the canvas convention: rgb is the layer color over white paper, and alpha is coverage.
+// See BlendOutput in blend.rs for the output modes
+uniform sampler2D source_layer;
+uniform float opacity;
+uniform int blend_output;
+// a copy of the canvas before the composite, which is only bound for lighten and darken
+uniform sampler2D source_backdrop;
+
+out vec4 frag;
+
+void main() {
+    // the layer is the same size as the canvas, so we can fetch texels directly
+    vec4 layer = texelFetch(source_layer, ivec2(gl_FragCoord.xy), 0);
+
+    if (blend_output == 1) {
+        // the layer over white, faded toward white by the opacity
+        frag = vec4(mix(vec3(1.0), layer.rgb, opacity), 1.0);
+    } else if (blend_output == 2) {
+        frag = vec4(opacity * layer.a);
+    } else if (blend_output == 3 || blend_output == 4) {
+        // the straight layer color mixes the canvas toward the lighter (or darker) color
+        vec3 backdrop = texelFetch(source_backdrop, ivec2(gl_FragCoord.xy), 0).rgb;
+        vec3 rgb = layer.a > 0.0 ? (layer.rgb - 1.0 + layer.a) / layer.a : backdrop;
+        vec3 blended = blend_output == 3 ? max(backdrop, rgb) : min(backdrop, rgb);
+        float alpha = opacity * layer.a;
+        frag = vec4(mix(backdrop, blended, alpha), alpha);
+    } else {
+        // removing the white paper gives premultiplied alpha
+        frag = opacity * vec4(layer.rgb - 1.0 + layer.a, layer.a);
+    }
+}
@@ -4,6 +4,8 @@ uniform float discard_threshold;
 
 in float v_gamma;
 in float v_colormap_bias;
+in vec4 v_tint;
+in float v_opacity;
 // we accept v_texcoords as input, but don't use it in this version of the shader
 in vec2 v_texcoords;
 in vec2 v_maskcoords;
@@ -13,13 +15,14 @@ out vec4 frag;
 
 void main() {
     vec4 mask = texture(source_mask, v_maskcoords);
-    vec4 color = texture(source_colormap, v_colorcoords, v_colormap_bias);
+    vec4 sampled = texture(source_colormap, v_colorcoords, v_colormap_bias);
+    vec4 color = vec4(mix(sampled.rgb, v_tint.rgb, v_tint.a), sampled.a);
 
     if (mask.r < discard_threshold) {
         discard;
     }
 
-    // now we compute the final color, with premultiplied alpha (for better blending on the first several passes)
-    float alpha_final = pow(mask.x * color.a, v_gamma);
-    frag = vec4(alpha_final * color.rgb, alpha_final);
+    // now we compute the final color, for the blend mode
+    float alpha_final = pow(mask.x * color.a, v_gamma) * v_opacity;
+    frag = blend(color.rgb, clip(alpha_final));
 }
\ No newline at end of file
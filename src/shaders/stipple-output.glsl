@@ -0,0 +1,39 @@
+// Computes the stipple output color for the blend mode.  See BlendOutput in blend.rs
+uniform int blend_output;
+uniform sampler2D source_clip;
+uniform bool clip_enabled;
+// a copy of the canvas before the draw, which is only bound for lighten and darken
+uniform sampler2D source_backdrop;
+
+in vec2 v_clipcoords;
+
+// attenuates the alpha by the layer's clip mask, in canvas coordinates
+float clip(float alpha) {
+    if (!clip_enabled) {
+        return alpha;
+    }
+
+    return alpha * texture(source_clip, v_clipcoords).x;
+}
+
+vec4 blend(vec3 rgb, float alpha) {
+    if (blend_output == 1) {
+        // multiply leaves the canvas unchanged where the stipple is transparent
+        return vec4(mix(vec3(1.0), rgb, alpha), 1.0);
+    } else if (blend_output == 2) {
+        // erasing moves the color toward white paper, and the alpha blend removes coverage
+        return vec4(alpha);
+    } else if (blend_output == 3) {
+        // lighten mixes the canvas toward the lighter color, and the max blend keeps overlapping stipples
+        vec3 backdrop = texelFetch(source_backdrop, ivec2(gl_FragCoord.xy), 0).rgb;
+        return vec4(mix(backdrop, max(backdrop, rgb), alpha), alpha);
+    } else if (blend_output == 4) {
+        // darken mixes the canvas toward the darker color, and the min blend keeps overlapping stipples
+        vec3 backdrop = texelFetch(source_backdrop, ivec2(gl_FragCoord.xy), 0).rgb;
+        return vec4(mix(backdrop, min(backdrop, rgb), alpha), alpha);
+    }
+
+    // premultiplied alpha (for better blending on the first several passes)
+    return vec4(alpha * rgb, alpha);
+}
+
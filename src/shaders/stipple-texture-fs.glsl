@@ -8,6 +8,8 @@ uniform float discard_threshold;
 
 in float v_gamma;
 in float v_colormap_bias;
+in vec4 v_tint;
+in float v_opacity;
 in vec2 v_texcoords;
 in vec2 v_maskcoords;
 in vec2 v_colorcoords;
@@ -17,7 +19,9 @@ out vec4 frag;
 void main() {
     vec4 mask = texture(source_mask, v_maskcoords);
     vec4 tex = texture(source_texture, v_texcoords);
-    vec4 color = texture(source_colormap, v_colorcoords, v_colormap_bias);
+    vec4 sampled = texture(source_colormap, v_colorcoords, v_colormap_bias);
+    // the tint is applied before the lightness adjustment, so the texture modulates the tinted color
+    vec4 color = vec4(mix(sampled.rgb, v_tint.rgb, v_tint.a), sampled.a);
 
     if (mask.r < discard_threshold) {
         discard;
@@ -76,7 +80,7 @@ void main() {
     float g = min(n * color.g, 1.0);
     float b = min(n * color.b, 1.0);
 
-    // now we compute the final color, with premultiplied alpha (for better blending on the first several passes)
-    float alpha_final = pow(mask.x * color.a, v_gamma);
-    frag = vec4(alpha_final * r, alpha_final * g, alpha_final * b, alpha_final);
+    // now we compute the final color, for the blend mode
+    float alpha_final = pow(mask.x * color.a, v_gamma) * v_opacity;
+    frag = blend(vec3(r, g, b), clip(alpha_final));
 }
\ No newline at end of file
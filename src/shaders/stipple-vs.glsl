@@ -1,18 +1,30 @@
 in vec2 position;
+// the rows of the linear part of the stipple transform, and the offset
+in vec4 transform;
+in vec2 transform_offset;
 in vec2 translation;
 in float rotation;
 in float gamma;
 in vec2 scale;
 in vec2 colormap_scale;
+in vec2 colormap_translation;
+in float colormap_rotation;
 in float texture_rotation;
+in vec4 tint;
+in float opacity;
 
 out vec2 v_maskcoords;
 out vec2 v_texcoords;
 out vec2 v_colorcoords;
 out float v_gamma;
 out float v_colormap_bias;
+out vec4 v_tint;
+out float v_opacity;
+out vec2 v_clipcoords;
 
 uniform float aspect_ratio;
+// maps canvas coordinates to the tile being rendered: (scale, offset)
+uniform vec4 tile_transform;
 
 vec2 rotate(vec2 v, float a) {
     float s = sin(a);
@@ -21,8 +33,12 @@ vec2 rotate(vec2 v, float a) {
     return m * v;
 }
 
+vec2 transformed_position() {
+    return vec2(dot(transform.xy, position), dot(transform.zw, position)) + transform_offset;
+}
+
 vec4 vertex_position() {
-    vec2 scaled = scale * position;
+    vec2 scaled = scale * transformed_position();
     vec2 rotated =  rotate(scaled, rotation);
     vec2 aspected = vec2(1.0 / aspect_ratio, 1.0) * rotated;
     return vec4(translation + aspected, 0.0, 1.0);
@@ -41,18 +57,23 @@ vec2 texture_position() {
 }
 
 vec2 colormap_position() {
-    vec2 scaled = scale * colormap_scale * position;
-    vec2 rotated =  rotate(scaled, rotation);
+    vec2 scaled = scale * colormap_scale * transformed_position();
+    vec2 rotated =  rotate(scaled, rotation + colormap_rotation);
     vec2 aspected = vec2(1.0 / aspect_ratio, 1.0) * rotated;
-    vec2 translated = translation + aspected;
+    vec2 translated = translation + colormap_translation + aspected;
     return vec2(0.5, 0.5) + vec2(0.5, -0.5) * translated;
 }
 
 void main() {
-    gl_Position = vertex_position();
+    vec4 position = vertex_position();
+    gl_Position = vec4(position.xy * tile_transform.xy + tile_transform.zw, position.zw);
     v_maskcoords = mask_position();
     v_texcoords = texture_position();
     v_colorcoords = colormap_position();
     v_gamma = gamma;
     v_colormap_bias = log2(2.0/(colormap_scale.x + colormap_scale.y));
+    v_tint = tint;
+    v_opacity = opacity;
+    // the clip mask covers the canvas, with the first row at the top
+    v_clipcoords = vec2(0.5, 0.5) + vec2(0.5, -0.5) * position.xy;
 }
\ No newline at end of file
@@ -0,0 +1,491 @@
+use crate::backend::{BackendFrame, DaliBackend, FrameCallback, Tile};
+use crate::blend::BlendMode;
+use crate::error::DaliError;
+use crate::Stipple;
+
+pub use raster::SoftwareCanvas;
+pub use texels::SoftwareTexels;
+use texels::Wrap;
+
+mod raster;
+mod texels;
+
+/// Renders Dali canvases on the CPU, without a GPU or OpenGL driver.
+/// Use it with `DaliPipeline::new(SoftwareRenderer::new())`.
+///
+/// The software renderer reproduces the stipple shaders (rotation, aspect correction, colormap scale and mipmap bias,
+/// texture lightness, gamma and blend modes), so output closely matches the OpenGL backend.
+/// It is much slower than the GPU, but deterministic, which makes it useful for tests and servers without GL.
+///
+/// Texture renderers are GLSL shaders, so they are not supported.  Use texture_from_image instead.
+#[derive(Default)]
+pub struct SoftwareRenderer {}
+
+impl SoftwareRenderer {
+    pub fn new() -> SoftwareRenderer {
+        Self::default()
+    }
+}
+
+impl DaliBackend for SoftwareRenderer {
+    type Colormap = SoftwareTexels;
+    type Mask = SoftwareTexels;
+    type Texture = SoftwareTexels;
+    type Target = SoftwareCanvas;
+
+    fn upload_colormap(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+    ) -> Result<SoftwareTexels, DaliError> {
+        Ok(SoftwareTexels::new(
+            size,
+            4,
+            texels.to_vec(),
+            0,
+            Wrap::MirroredRepeat,
+        ))
+    }
+
+    fn upload_mask(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+        mipmaps: usize,
+    ) -> Result<SoftwareTexels, DaliError> {
+        Ok(SoftwareTexels::new(
+            size,
+            1,
+            texels.to_vec(),
+            mipmaps,
+            Wrap::ClampToEdge,
+        ))
+    }
+
+    fn upload_texture(
+        &mut self,
+        size: [u32; 2],
+        texels: &[f32],
+        mipmaps: usize,
+    ) -> Result<SoftwareTexels, DaliError> {
+        self.upload_mask(size, texels, mipmaps)
+    }
+
+    fn max_target_size(&self) -> u32 {
+        u32::MAX
+    }
+
+    fn create_target(&mut self, size: [u32; 2]) -> Result<SoftwareCanvas, DaliError> {
+        Ok(SoftwareCanvas::new(size))
+    }
+
+    fn render_frame(
+        &mut self,
+        target: &mut SoftwareCanvas,
+        tile: Tile,
+        clear_color: [f32; 4],
+        draw: &mut FrameCallback<'_, Self>,
+    ) -> Result<(), DaliError> {
+        target.set_tile(tile);
+        target.clear(clear_color);
+        draw(target)
+    }
+
+    fn read_target(&mut self, target: &SoftwareCanvas) -> Result<Vec<f32>, DaliError> {
+        Ok(target.texels().to_vec())
+    }
+
+    fn read_target_rows(
+        &mut self,
+        target: &SoftwareCanvas,
+        first: u32,
+        rows: u32,
+    ) -> Result<Vec<f32>, DaliError> {
+        Ok(target.texel_rows(first, rows).to_vec())
+    }
+}
+
+impl BackendFrame<SoftwareRenderer> for SoftwareCanvas {
+    fn draw_stipples(
+        &mut self,
+        colormap: &SoftwareTexels,
+        mask: &SoftwareTexels,
+        texture: Option<&SoftwareTexels>,
+        clip: Option<&SoftwareTexels>,
+        blend_mode: BlendMode,
+        stipples: &[Stipple],
+    ) -> Result<(), DaliError> {
+        self.copy_backdrop(blend_mode);
+        for stipple in stipples {
+            self.draw_stipple(stipple, mask, colormap, texture, clip, blend_mode);
+        }
+
+        Ok(())
+    }
+
+    fn composite(
+        &mut self,
+        layer: &SoftwareCanvas,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> Result<(), DaliError> {
+        self.composite_layer(layer, opacity, blend_mode);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::{SoftwareCanvas, SoftwareRenderer};
+    use crate::backend::{BackendFrame, DaliBackend};
+    use crate::testing;
+    use crate::{
+        Background, BlendMode, CancelToken, DaliError, Dither, ImageFileFormat, RenderProgress,
+        Scene, Stipple,
+    };
+
+    #[test]
+    fn renders_stipple_in_center() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(SoftwareRenderer::new(), |_x, _y| [1.0, 0.0, 0.0, 1.0]);
+
+        let image = pipeline.render_canvas([32, 32], |canvas_gate| {
+            canvas_gate.layer(&colormap, |layer_gate| {
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.draw(Stipple::default().with_scale([0.5, 0.5]));
+                });
+            });
+        });
+
+        assert_eq!(&[255, 0, 0, 255], &image.get_pixel(16, 16).0);
+        assert_eq!(&[255, 0, 0, 255], &image.get_pixel(8, 8).0);
+        assert_eq!(&[255, 255, 255, 255], &image.get_pixel(7, 7).0);
+    }
+
+    #[test]
+    fn renders_scene_at_multiple_sizes() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(SoftwareRenderer::new(), |_x, _y| [1.0, 0.0, 0.0, 1.0]);
+
+        let mut scene = Scene::from_fn(|canvas_gate| {
+            canvas_gate.layer(&colormap, |layer_gate| {
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.draw(Stipple::default().with_scale([0.5, 0.5]));
+                });
+            });
+        });
+
+        let preview = pipeline.render_scene([16, 16], &scene);
+        let print = pipeline.render_scene([64, 64], &scene);
+        assert_eq!(&[255, 0, 0, 255], &preview.get_pixel(8, 8).0);
+        assert_eq!(&[255, 0, 0, 255], &print.get_pixel(32, 32).0);
+        assert_eq!(&[255, 255, 255, 255], &print.get_pixel(8, 8).0);
+
+        for layer in scene.canvas_mut().layers_mut() {
+            for stipples in layer.stipples_mut() {
+                stipples.instances_mut().clear();
+            }
+        }
+
+        let blank = pipeline.render_scene([16, 16], &scene);
+        assert_eq!(&[255, 255, 255, 255], &blank.get_pixel(8, 8).0);
+    }
+
+    #[test]
+    fn tiles_match_whole_canvas() {
+        let (mut pipeline, colormap, _) =
+            testing::pipeline(SoftwareRenderer::new(), |x, y| [x, y, 0.5, 1.0]);
+        let mask = pipeline.mask_from_image(
+            image::GrayImage::from_fn(8, 8, |x, y| image::Luma([((x + y) * 16) as u8])),
+            2,
+        );
+        let clip = pipeline.clip_from_image(image::GrayImage::from_fn(6, 6, |x, _y| {
+            image::Luma([if x < 3 { 255 } else { 64 }])
+        }));
+
+        let scene = Scene::from_fn(|canvas_gate| {
+            canvas_gate.layer_with_clip(&colormap, &clip, |layer_gate| {
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.draw(Stipple::default().with_scale([0.7, 0.5]).with_rotation(0.3));
+                });
+            });
+            canvas_gate.layer(&colormap, |layer_gate| {
+                layer_gate.set_opacity(0.5);
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.set_blend_mode(BlendMode::Multiply);
+                    stipple_gate.draw(Stipple::default().with_translation([0.4, -0.3]));
+                });
+            });
+        });
+
+        let whole = pipeline.render_scene([37, 29], &scene);
+        let tiled = pipeline.render_scene_tiled([37, 29], [16, 8], &scene);
+        assert_eq!(whole.into_raw(), tiled.into_raw());
+    }
+
+    #[test]
+    fn renders_canvas_to_file_in_strips() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(SoftwareRenderer::new(), |x, y| [x, y, 0.5, 1.0]);
+
+        // wider and taller than a strip tile, so the file is stitched from several tiles
+        let size = [2100, 300];
+        let scene = Scene::from_fn(|canvas_gate| {
+            canvas_gate.layer(&colormap, |layer_gate| {
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.draw(Stipple::default().with_scale([0.1, 0.4]));
+                });
+            });
+        });
+        let expected = pipeline.render_scene(size, &scene);
+
+        let dir = std::env::temp_dir();
+        for (background, extension) in [(true, "png"), (false, "tiff")].iter() {
+            let path = dir.join(format!("dali-strips-{}.{}", std::process::id(), extension));
+            let format = ImageFileFormat::from_path(&path).expect("Should have a format");
+
+            pipeline.set_background_encoding(*background);
+            pipeline.render_scene_to_file(size, &path, format, &scene);
+
+            let decoded = image::open(&path)
+                .expect("Should have decoded file")
+                .to_rgba();
+            std::fs::remove_file(&path).expect("Should have removed file");
+            assert_eq!(expected.to_vec(), decoded.to_vec(), "{}", extension);
+        }
+    }
+
+    #[test]
+    fn renders_high_bit_depth() {
+        let (mut pipeline, _, mask) = testing::pipeline(SoftwareRenderer::new(), |_x, _y| [0.0; 4]);
+        let colormap = pipeline.colormap([16, 1], |x, _y| [x, 0.5, 1.0 - x, 1.0]);
+
+        let scene = Scene::from_fn(|canvas_gate| {
+            canvas_gate.layer(&colormap, |layer_gate| {
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.draw(Stipple::default().with_scale([2.0, 1.0]));
+                });
+            });
+        });
+
+        let size = [48, 8];
+        let rounded = pipeline.render_scene(size, &scene);
+        let deep = pipeline.render_scene_16(size, &scene);
+        let float = pipeline.render_scene_f32(size, &scene);
+
+        assert_eq!(128, rounded.get_pixel(20, 4).0[1]);
+        for ((a, b), c) in rounded.pixels().zip(deep.pixels()).zip(float.pixels()) {
+            for i in 0..4 {
+                assert_eq!(a.0[i], (c.0[i] * 255.0).round() as u8);
+                assert_eq!(b.0[i], (c.0[i] * 65535.0).round() as u16);
+            }
+        }
+
+        // dithering follows canvas pixels, so tiled renders match
+        pipeline.set_dither(Dither::BlueNoise);
+        let whole = pipeline.render_scene(size, &scene);
+        let tiled = pipeline.render_scene_tiled(size, [20, 5], &scene);
+        assert_eq!(whole.to_vec(), tiled.to_vec());
+        assert_ne!(rounded.to_vec(), whole.to_vec());
+    }
+
+    #[test]
+    fn renders_transparent_paper() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(SoftwareRenderer::new(), |_x, _y| [1.0, 0.0, 0.0, 0.5]);
+
+        let scene = Scene::from_fn(|canvas_gate| {
+            canvas_gate.layer(&colormap, |layer_gate| {
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.draw(Stipple::default().with_scale([0.5, 0.5]));
+                });
+            });
+        });
+
+        let opaque = pipeline.render_scene([32, 32], &scene);
+        assert_eq!(&[255, 128, 128, 255], &opaque.get_pixel(16, 16).0);
+
+        pipeline.set_transparent(true);
+        let transparent = pipeline.render_scene([32, 32], &scene);
+        assert_eq!(&[255, 0, 0, 128], &transparent.get_pixel(16, 16).0);
+        assert_eq!(&[0, 0, 0, 0], &transparent.get_pixel(4, 4).0);
+
+        let tiled = pipeline.render_scene_tiled([32, 32], [10, 10], &scene);
+        assert_eq!(transparent.to_vec(), tiled.to_vec());
+
+        // PNG files keep the alpha channel
+        let path = std::env::temp_dir().join(format!("dali-alpha-{}.png", std::process::id()));
+        pipeline.render_scene_to_file([32, 32], &path, ImageFileFormat::Png, &scene);
+        let decoded = image::open(&path)
+            .expect("Should have decoded file")
+            .to_rgba();
+        std::fs::remove_file(&path).expect("Should have removed file");
+        assert_eq!(transparent.to_vec(), decoded.to_vec());
+    }
+
+    #[test]
+    fn reports_progress_and_cancels() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(SoftwareRenderer::new(), |x, y| [x, y, 0.5, 1.0]);
+
+        let scene = Scene::from_fn(|canvas_gate| {
+            for _ in 0..2 {
+                canvas_gate.layer(&colormap, |layer_gate| {
+                    layer_gate.stipple(&mask, |stipple_gate| {
+                        (0..1000).for_each(|_| {
+                            stipple_gate.draw(Stipple::default().with_scale([0.1, 0.1]))
+                        })
+                    });
+                });
+            }
+        });
+
+        let reports = Rc::new(RefCell::new(Vec::<RenderProgress>::new()));
+        let recorded = reports.clone();
+        pipeline.set_progress_callback(move |progress| recorded.borrow_mut().push(*progress));
+        pipeline.render_scene_tiled([16, 16], [8, 16], &scene);
+
+        // each layer is drawn in two chunks, in each of the two tiles, but layers and batches are counted once
+        let reports = reports.borrow();
+        assert_eq!(12, reports.len());
+        let last = reports.last().expect("Should have reported progress");
+        assert_eq!((2, 2), (last.layers_done, last.layers_total));
+        assert_eq!((4000, 4000), (last.stipples_drawn, last.stipples_total));
+        assert_eq!(2, pipeline.batch_count());
+        assert!(reports.windows(2).all(|pair| {
+            pair[0].stipples_drawn <= pair[1].stipples_drawn
+                && pair[0].layers_done <= pair[1].layers_done
+        }));
+
+        // cancelling from the callback stops the render before the next chunk
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        pipeline.set_cancel_token(cancel.clone());
+        pipeline.set_progress_callback(move |progress| {
+            if progress.layers_done == 1 {
+                token.cancel();
+            }
+        });
+        match pipeline.try_render_scene([16, 16], &scene) {
+            Err(DaliError::Cancelled) => {}
+            _ => panic!("Expected the render to be cancelled"),
+        }
+        assert_eq!(1, pipeline.batch_count());
+
+        cancel.reset();
+        pipeline.clear_progress_callback();
+        assert!(pipeline.try_render_scene([16, 16], &scene).is_ok());
+    }
+
+    #[test]
+    fn streams_same_output_as_canvas() {
+        let (mut pipeline, colormap, _) =
+            testing::pipeline(SoftwareRenderer::new(), |x, y| [x, y, 0.5, 1.0]);
+        let mask = pipeline.mask_from_image(
+            image::GrayImage::from_fn(8, 8, |x, y| image::Luma([((x * y) * 4) as u8])),
+            1,
+        );
+
+        let stipple = |i: usize| {
+            let t = i as f32 / 700.0;
+            Stipple::default()
+                .with_translation([t * 2.0 - 1.0, (t * 17.0).sin() * 0.8])
+                .with_scale([0.1, 0.1])
+                .with_rotation(t * 6.0)
+        };
+
+        let buffered = pipeline.render_canvas([32, 24], |canvas| {
+            canvas.layer(&colormap, |layer| {
+                layer.stipple(&mask, |stipples| {
+                    (0..700).for_each(|i| stipples.draw(stipple(i)))
+                });
+            });
+        });
+
+        let streamed = pipeline.stream_canvas([32, 24], |canvas| {
+            canvas.layer(&colormap, |layer| {
+                layer.stipple(&mask, |stipples| {
+                    (0..700).for_each(|i| stipples.draw(stipple(i)))
+                });
+            });
+        });
+
+        assert_eq!(buffered.into_raw(), streamed.into_raw());
+    }
+
+    #[test]
+    fn continues_from_image_background() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(SoftwareRenderer::new(), |x, y| [x, y, 0.5, 1.0]);
+
+        let first = pipeline.render_canvas([24, 16], |canvas_gate| {
+            canvas_gate.set_background(Background::Color([0.2, 0.4, 0.6, 1.0]));
+            canvas_gate.layer(&colormap, |layer_gate| {
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.draw(Stipple::default().with_translation([0.5, 0.5]));
+                });
+            });
+        });
+
+        let second = pipeline.render_canvas([24, 16], |canvas_gate| {
+            canvas_gate.set_background(Background::Image(&first));
+        });
+
+        assert_eq!(&[51, 102, 153, 255], &first.get_pixel(0, 15).0);
+        assert_eq!(first.into_raw(), second.into_raw());
+    }
+
+    #[test]
+    fn blends_with_mode() {
+        let mut renderer = SoftwareRenderer::new();
+        let half_red = renderer
+            .upload_colormap([1, 1], &[1.0, 0.0, 0.0, 0.5])
+            .unwrap();
+        let mask = renderer.upload_mask([1, 1], &[1.0], 0).unwrap();
+        let mut canvas = SoftwareCanvas::new([2, 2]);
+
+        let mut draw = |mode: BlendMode| {
+            canvas.clear([0.5, 0.5, 0.5, 1.0]);
+            canvas
+                .draw_stipples(&half_red, &mask, None, None, mode, &[Stipple::default()])
+                .unwrap();
+            canvas.texels()[0..4].to_vec()
+        };
+
+        assert_eq!(vec![0.75, 0.25, 0.25, 1.0], draw(BlendMode::Normal));
+        assert_eq!(vec![0.5, 0.25, 0.25, 1.0], draw(BlendMode::Multiply));
+        assert_eq!(vec![0.75, 0.5, 0.5, 1.0], draw(BlendMode::Screen));
+        assert_eq!(vec![0.75, 0.5, 0.5, 1.0], draw(BlendMode::Lighten));
+        assert_eq!(vec![0.5, 0.25, 0.25, 1.0], draw(BlendMode::Darken));
+        assert_eq!(vec![0.75, 0.75, 0.75, 0.5], draw(BlendMode::EraseAlpha));
+    }
+
+    #[test]
+    fn erases_toward_transparent_paper() {
+        let (mut pipeline, colormap, mask) =
+            testing::pipeline(SoftwareRenderer::new(), |_x, _y| [1.0, 1.0, 1.0, 0.5]);
+        pipeline.set_transparent(true);
+
+        let scene = Scene::from_fn(|canvas_gate| {
+            canvas_gate.set_background(Background::Color([0.5, 0.5, 0.5, 1.0]));
+            canvas_gate.layer(&colormap, |layer_gate| {
+                layer_gate.stipple(&mask, |stipple_gate| {
+                    stipple_gate.set_blend_mode(BlendMode::EraseAlpha);
+                    stipple_gate.draw(Stipple::default());
+                });
+            });
+        });
+
+        // PNG files keep the alpha channel, and erasing half of opaque grey leaves grey at half coverage
+        let path = std::env::temp_dir().join(format!("dali-erase-{}.png", std::process::id()));
+        pipeline.render_scene_to_file([16, 16], &path, ImageFileFormat::Png, &scene);
+        let decoded = image::open(&path)
+            .expect("Should have decoded file")
+            .to_rgba();
+        std::fs::remove_file(&path).expect("Should have removed file");
+        assert_eq!(&[128, 128, 128, 128], &decoded.get_pixel(8, 8).0);
+    }
+}
@@ -0,0 +1,401 @@
+use crate::backend::Tile;
+use crate::blend::{BlendMode, BlendOutput};
+use crate::software::texels::SoftwareTexels;
+use crate::Stipple;
+
+/// An RGBA render target, stored bottom row first (like an OpenGL framebuffer).
+/// RGB is the color over white paper, and alpha is the coverage, like the OpenGL targets.
+pub struct SoftwareCanvas {
+    width: u32,
+    height: u32,
+    // the region of the canvas covered by this target, which is set by each render
+    tile: Tile,
+    texels: Vec<f32>,
+    // a copy of the texels, which lighten and darken read the canvas from, like the backdrop in backend/gl.rs
+    backdrop: Vec<f32>,
+}
+
+/// A 2x2 matrix, stored row-major
+type Mat2 = [[f32; 2]; 2];
+
+impl SoftwareCanvas {
+    pub(crate) fn new(size: [u32; 2]) -> SoftwareCanvas {
+        SoftwareCanvas {
+            width: size[0],
+            height: size[1],
+            tile: Tile::whole(size),
+            texels: vec![0.0; (size[0] * size[1]) as usize * 4],
+            backdrop: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_tile(&mut self, tile: Tile) {
+        assert_eq!(
+            [self.width, self.height],
+            tile.size,
+            "Tiles should have the same size as the target"
+        );
+        self.tile = tile;
+    }
+
+    pub(crate) fn clear(&mut self, clear_color: [f32; 4]) {
+        for texel in self.texels.chunks_mut(4) {
+            texel.copy_from_slice(&clear_color);
+        }
+    }
+
+    pub(crate) fn texels(&self) -> &[f32] {
+        &self.texels
+    }
+
+    /// The texels of `rows` rows, starting with row `first` from the bottom
+    pub(crate) fn texel_rows(&self, first: u32, rows: u32) -> &[f32] {
+        let row = self.width as usize * 4;
+        let start = first as usize * row;
+        &self.texels[start..start + rows as usize * row]
+    }
+
+    /// Copies the texels into the backdrop, before a draw with a blend mode which reads the canvas
+    pub(crate) fn copy_backdrop(&mut self, blend_mode: BlendMode) {
+        if blend_mode.output().reads_backdrop() {
+            self.backdrop.clone_from(&self.texels);
+        }
+    }
+
+    /// Rasterizes a stipple quad, following stipple-vs.glsl and stipple-fs.glsl (or stipple-texture-fs.glsl)
+    pub(crate) fn draw_stipple(
+        &mut self,
+        stipple: &Stipple,
+        mask: &SoftwareTexels,
+        colormap: &SoftwareTexels,
+        texture: Option<&SoftwareTexels>,
+        clip: Option<&SoftwareTexels>,
+        blend_mode: BlendMode,
+    ) {
+        // stipples are positioned on the whole canvas, and only the pixels in the tile are rasterized
+        let [canvas_width, canvas_height] =
+            [self.tile.canvas[0] as f32, self.tile.canvas[1] as f32];
+        let origin = self.tile.origin();
+        let aspect = canvas_width / canvas_height;
+
+        // the vertex shader is affine in the quad position, so the quad can be rasterized
+        // by inverting the transform at each pixel center
+        let [row_x, row_y] = stipple.transform;
+        let transform = [[row_x[0], row_x[1]], [row_y[0], row_y[1]]];
+        let transform_offset = [row_x[2], row_y[2]];
+
+        let placement =
+            linear(|p| aspected(rotate(mul(stipple.scale, p), stipple.rotation), aspect));
+        let position = compose(placement, transform);
+        let translation = add(stipple.translation, apply(placement, transform_offset));
+        let inverse = match invert(position) {
+            Some(inverse) => inverse,
+            None => return,
+        };
+
+        let colormap_scale = mul(stipple.scale, stipple.colormap_scale);
+        let colormap_rotation = stipple.rotation + stipple.colormap_rotation;
+        let colormap_placement =
+            linear(|p| aspected(rotate(mul(colormap_scale, p), colormap_rotation), aspect));
+        let colormap_position = compose(colormap_placement, transform);
+        let colormap_translation = add(
+            add(stipple.translation, stipple.colormap_translation),
+            apply(colormap_placement, transform_offset),
+        );
+        let texture_position = linear(|p| {
+            let rotated = rotate(p, stipple.texture_rotation);
+            [rotated[0] / 2f32.sqrt(), rotated[1] / 2f32.sqrt()]
+        });
+
+        let flip = [[0.5, 0.0], [0.0, -0.5]];
+        let mask_jacobian = flip;
+        let colormap_jacobian = compose(flip, colormap_position);
+        let texture_jacobian = compose(flip, texture_position);
+
+        // screen-space derivatives of the quad position, for mipmap selection
+        let dp_dx = apply(inverse, [2.0 / canvas_width, 0.0]);
+        let dp_dy = apply(inverse, [0.0, 2.0 / canvas_height]);
+
+        let mask_lod = lod(mask_jacobian, dp_dx, dp_dy, mask.size());
+        let colormap_bias = (2.0 / (stipple.colormap_scale[0] + stipple.colormap_scale[1])).log2();
+        let colormap_lod = lod(colormap_jacobian, dp_dx, dp_dy, colormap.size()) + colormap_bias;
+        let texture_lod = texture.map(|t| lod(texture_jacobian, dp_dx, dp_dy, t.size()));
+
+        let (x_range, y_range) = self.bounds(translation, position);
+
+        for y in y_range.0..y_range.1 {
+            for x in x_range.0..x_range.1 {
+                // the pixel center on the canvas
+                let center = [(x + origin[0]) as f32 + 0.5, (y + origin[1]) as f32 + 0.5];
+                let ndc = [
+                    center[0] / canvas_width * 2.0 - 1.0,
+                    center[1] / canvas_height * 2.0 - 1.0,
+                ];
+                let p = apply(inverse, sub(ndc, translation));
+                if p[0].abs() > 1.0 || p[1].abs() > 1.0 {
+                    continue;
+                }
+
+                let mask_uv = [0.5 + 0.5 * p[0], 0.5 - 0.5 * p[1]];
+                let colormap_ndc = add(colormap_translation, apply(colormap_position, p));
+                let colormap_uv = [0.5 + 0.5 * colormap_ndc[0], 0.5 - 0.5 * colormap_ndc[1]];
+
+                let mask_texel = mask.sample(mask_uv, mask_lod);
+                let sampled = colormap.sample(colormap_uv, colormap_lod);
+                let color = tint(sampled, stipple.tint);
+
+                let rgb = match (texture, texture_lod) {
+                    (Some(texture), Some(texture_lod)) => {
+                        let rotated = apply(texture_position, p);
+                        let texture_uv = [0.5 + 0.5 * rotated[0], 0.5 - 0.5 * rotated[1]];
+                        let tex = texture.sample(texture_uv, texture_lod);
+                        adjust_lightness([color[0], color[1], color[2]], tex[0])
+                    }
+                    _ => [color[0], color[1], color[2]],
+                };
+
+                let mut alpha = (mask_texel[0] * color[3]).powf(stipple.gamma) * stipple.opacity;
+                if let Some(clip) = clip {
+                    // the clip covers the canvas, with the first row at the top
+                    let clip_uv = [center[0] / canvas_width, 1.0 - center[1] / canvas_height];
+                    alpha *= clip.sample(clip_uv, 0.0)[0];
+                }
+
+                let source = output(blend_mode.output(), rgb, alpha);
+                self.blend(x, y, source, blend_mode);
+            }
+        }
+    }
+
+    /// Composites an isolated layer with the same size, following composite-fs.glsl
+    pub(crate) fn composite_layer(
+        &mut self,
+        layer: &SoftwareCanvas,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) {
+        assert_eq!(
+            (self.width, self.height),
+            (layer.width, layer.height),
+            "Layers should have the same size as the canvas"
+        );
+
+        self.copy_backdrop(blend_mode);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let offset = ((y * self.width + x) * 4) as usize;
+                let texel = &layer.texels[offset..offset + 4];
+                let alpha = texel[3];
+
+                let source = match blend_mode.output() {
+                    BlendOutput::Premultiplied => [
+                        opacity * (texel[0] - 1.0 + alpha),
+                        opacity * (texel[1] - 1.0 + alpha),
+                        opacity * (texel[2] - 1.0 + alpha),
+                        opacity * alpha,
+                    ],
+                    BlendOutput::OverWhite => {
+                        let mix = |c: f32| (1.0 - opacity) + c * opacity;
+                        [mix(texel[0]), mix(texel[1]), mix(texel[2]), 1.0]
+                    }
+                    BlendOutput::Erase => [opacity * alpha; 4],
+                    BlendOutput::BackdropMax | BlendOutput::BackdropMin if alpha > 0.0 => [
+                        (texel[0] - 1.0 + alpha) / alpha,
+                        (texel[1] - 1.0 + alpha) / alpha,
+                        (texel[2] - 1.0 + alpha) / alpha,
+                        opacity * alpha,
+                    ],
+                    BlendOutput::BackdropMax | BlendOutput::BackdropMin => [0.0, 0.0, 0.0, 0.0],
+                };
+
+                self.blend(x, y, source, blend_mode);
+            }
+        }
+    }
+
+    /// Returns the pixel ranges of the target covered by the transformed quad, clamped to the tile
+    fn bounds(&self, translation: [f32; 2], position: Mat2) -> ((u32, u32), (u32, u32)) {
+        let mut min = [f32::INFINITY; 2];
+        let mut max = [f32::NEG_INFINITY; 2];
+        for corner in [[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]].iter() {
+            let ndc = add(translation, apply(position, *corner));
+            for i in 0..2 {
+                min[i] = min[i].min(ndc[i]);
+                max[i] = max[i].max(ndc[i]);
+            }
+        }
+
+        let origin = self.tile.origin();
+        let range = |i: usize| {
+            let (canvas, origin, len) = (
+                self.tile.canvas[i] as f32,
+                origin[i] as f32,
+                self.tile.size[i] as f32,
+            );
+            // pixel centers at (i + 0.5), in ndc, relative to the tile
+            let first = ((min[i] + 1.0) / 2.0 * canvas - 0.5 - origin)
+                .ceil()
+                .max(0.0);
+            let last = ((max[i] + 1.0) / 2.0 * canvas - 0.5 - origin).floor() + 1.0;
+            let last = last.min(len);
+            if last <= first {
+                (0, 0)
+            } else {
+                (first as u32, last as u32)
+            }
+        };
+
+        (range(0), range(1))
+    }
+
+    /// Blends the source into the canvas, following the GPU blend state for each mode in backend/gl.rs
+    fn blend(&mut self, x: u32, y: u32, source: [f32; 4], blend_mode: BlendMode) {
+        let offset = ((y * self.width + x) * 4) as usize;
+        let dest = &mut self.texels[offset..offset + 4];
+        let complement = 1.0 - source[3];
+        if blend_mode.output().reads_backdrop() {
+            // the source is straight, and mixes the canvas toward the lighter (or darker) color
+            let backdrop = &self.backdrop[offset..offset + 3];
+            for ((d, b), s) in dest.iter_mut().zip(backdrop).zip(source.iter()) {
+                *d = if blend_mode == BlendMode::Lighten {
+                    d.max(b * complement + b.max(*s) * source[3])
+                } else {
+                    d.min(b * complement + b.min(*s) * source[3])
+                };
+            }
+            dest[3] = dest[3].max(source[3]);
+            return;
+        }
+
+        if blend_mode == BlendMode::EraseAlpha {
+            // the color moves toward white paper, as the coverage is removed
+            for d in dest[0..3].iter_mut() {
+                *d = source[3] + *d * complement;
+            }
+            dest[3] *= complement;
+            return;
+        }
+
+        for (d, s) in dest.iter_mut().zip(source.iter()) {
+            *d = match blend_mode {
+                BlendMode::Normal => s + *d * complement,
+                BlendMode::Multiply => s * *d,
+                BlendMode::Screen => s + *d * (1.0 - s),
+                BlendMode::Lighten | BlendMode::Darken | BlendMode::EraseAlpha => {
+                    unreachable!("Lighten, darken and erase are blended separately")
+                }
+            };
+        }
+    }
+}
+
+/// Computes the shader output color for the blend mode, following stipple-output.glsl
+fn output(output: BlendOutput, rgb: [f32; 3], alpha: f32) -> [f32; 4] {
+    match output {
+        BlendOutput::Premultiplied => [alpha * rgb[0], alpha * rgb[1], alpha * rgb[2], alpha],
+        BlendOutput::OverWhite => {
+            let mix = |c: f32| (1.0 - alpha) + c * alpha;
+            [mix(rgb[0]), mix(rgb[1]), mix(rgb[2]), 1.0]
+        }
+        BlendOutput::Erase => [alpha; 4],
+        // mixed with the backdrop in SoftwareCanvas::blend
+        BlendOutput::BackdropMax | BlendOutput::BackdropMin => [rgb[0], rgb[1], rgb[2], alpha],
+    }
+}
+
+/// Mixes the color toward tint.rgb by tint.a, like mix() in GLSL
+fn tint(color: [f32; 4], tint: [f32; 4]) -> [f32; 4] {
+    let mix = |c: f32, t: f32| c * (1.0 - tint[3]) + t * tint[3];
+    [
+        mix(color[0], tint[0]),
+        mix(color[1], tint[1]),
+        mix(color[2], tint[2]),
+        color[3],
+    ]
+}
+
+/// The lightness adjustment from stipple-texture-fs.glsl.  See the shader for a description of the algorithm.
+fn adjust_lightness(color: [f32; 3], tex: f32) -> [f32; 3] {
+    let r2 = color[0] * color[0];
+    let g2 = color[1] * color[1];
+    let b2 = color[2] * color[2];
+    let lx = (0.299 * r2 + 0.587 * g2 + 0.114 * b2).sqrt();
+
+    let lx2 = lx * lx;
+    let lx3 = lx2 * lx;
+    let upper = -0.535_714 * lx2 + 1.53571 * lx + 0.018_571_4;
+    let lower = -0.717_593 * lx3 + 1.4246 * lx2 + 0.191_402 * lx + 0.002_222_22;
+
+    let l = lower + (upper - lower) * tex;
+    let n = 10.0 * 10f32.sqrt() * l / (299.0 * r2 + 587.0 * g2 + 114.0 * b2).sqrt();
+
+    [
+        (n * color[0]).min(1.0),
+        (n * color[1]).min(1.0),
+        (n * color[2]).min(1.0),
+    ]
+}
+
+/// The mipmap level of detail for a texture coordinate with the given jacobian (d uv / d p)
+fn lod(jacobian: Mat2, dp_dx: [f32; 2], dp_dy: [f32; 2], size: [u32; 2]) -> f32 {
+    let texels = |dp: [f32; 2]| {
+        let duv = apply(jacobian, dp);
+        let du = duv[0] * size[0] as f32;
+        let dv = duv[1] * size[1] as f32;
+        (du * du + dv * dv).sqrt()
+    };
+
+    texels(dp_dx).max(texels(dp_dy)).log2()
+}
+
+/// Same as the rotate() function in stipple-vs.glsl
+fn rotate(v: [f32; 2], a: f32) -> [f32; 2] {
+    let (s, c) = a.sin_cos();
+    [c * v[0] + s * v[1], -s * v[0] + c * v[1]]
+}
+
+fn aspected(v: [f32; 2], aspect: f32) -> [f32; 2] {
+    [v[0] / aspect, v[1]]
+}
+
+/// Captures a linear function of the quad position as a matrix
+fn linear<F: Fn([f32; 2]) -> [f32; 2]>(f: F) -> Mat2 {
+    let x = f([1.0, 0.0]);
+    let y = f([0.0, 1.0]);
+    [[x[0], y[0]], [x[1], y[1]]]
+}
+
+fn invert(m: Mat2) -> Option<Mat2> {
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    if det == 0.0 {
+        return None;
+    }
+
+    Some([
+        [m[1][1] / det, -m[0][1] / det],
+        [-m[1][0] / det, m[0][0] / det],
+    ])
+}
+
+fn compose(a: Mat2, b: Mat2) -> Mat2 {
+    linear(|p| apply(a, apply(b, p)))
+}
+
+fn apply(m: Mat2, v: [f32; 2]) -> [f32; 2] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1],
+        m[1][0] * v[0] + m[1][1] * v[1],
+    ]
+}
+
+fn mul(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] * b[0], a[1] * b[1]]
+}
+
+fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
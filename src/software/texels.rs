@@ -0,0 +1,185 @@
+/// Texture coordinate wrapping, matching the OpenGL wrap modes used by the GPU samplers
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Wrap {
+    ClampToEdge,
+    MirroredRepeat,
+}
+
+struct Level {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+/// A mipmapped texture in main memory, owned by a [SoftwareRenderer](crate::SoftwareRenderer).
+///
+/// Sampling matches the GPU samplers: bilinear magnification, and trilinear minification
+/// between box-filtered mip levels.
+pub struct SoftwareTexels {
+    levels: Vec<Level>,
+    channels: usize,
+    wrap: Wrap,
+}
+
+impl SoftwareTexels {
+    /// Creates a texture from row-major texels, starting with the top row,
+    /// and generates `mipmaps` additional levels.
+    pub(crate) fn new(
+        size: [u32; 2],
+        channels: usize,
+        data: Vec<f32>,
+        mipmaps: usize,
+        wrap: Wrap,
+    ) -> SoftwareTexels {
+        debug_assert_eq!((size[0] * size[1]) as usize * channels, data.len());
+
+        let mut levels = vec![Level {
+            width: size[0],
+            height: size[1],
+            data,
+        }];
+
+        for _ in 0..mipmaps {
+            let next = levels.last().unwrap().downsample(channels);
+            levels.push(next);
+        }
+
+        SoftwareTexels {
+            levels,
+            channels,
+            wrap,
+        }
+    }
+
+    pub(crate) fn size(&self) -> [u32; 2] {
+        [self.levels[0].width, self.levels[0].height]
+    }
+
+    /// Samples the texture at the normalized uv coordinate, at the given level of detail.
+    /// Missing channels are filled with (0, 0, 0, 1), like single-channel GPU textures.
+    pub(crate) fn sample(&self, uv: [f32; 2], lod: f32) -> [f32; 4] {
+        let max_level = self.levels.len() - 1;
+        if lod <= 0.0 || max_level == 0 {
+            return self.bilinear(&self.levels[0], uv);
+        }
+
+        let lod = lod.min(max_level as f32);
+        let lower = lod.floor() as usize;
+        let upper = (lower + 1).min(max_level);
+        let t = lod - lower as f32;
+
+        let a = self.bilinear(&self.levels[lower], uv);
+        if t == 0.0 {
+            return a;
+        }
+
+        let b = self.bilinear(&self.levels[upper], uv);
+        let mut mixed = [0.0; 4];
+        for i in 0..4 {
+            mixed[i] = a[i] + (b[i] - a[i]) * t;
+        }
+        mixed
+    }
+
+    fn bilinear(&self, level: &Level, uv: [f32; 2]) -> [f32; 4] {
+        let x = uv[0] * level.width as f32 - 0.5;
+        let y = uv[1] * level.height as f32 - 0.5;
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+
+        let x0 = x0 as i64;
+        let y0 = y0 as i64;
+        let x_indices = [
+            self.wrap.index(x0, level.width),
+            self.wrap.index(x0 + 1, level.width),
+        ];
+        let y_indices = [
+            self.wrap.index(y0, level.height),
+            self.wrap.index(y0 + 1, level.height),
+        ];
+
+        let weights = [
+            (1.0 - tx) * (1.0 - ty),
+            tx * (1.0 - ty),
+            (1.0 - tx) * ty,
+            tx * ty,
+        ];
+        let corners = [
+            (x_indices[0], y_indices[0]),
+            (x_indices[1], y_indices[0]),
+            (x_indices[0], y_indices[1]),
+            (x_indices[1], y_indices[1]),
+        ];
+
+        let mut texel = [0.0, 0.0, 0.0, 1.0];
+        for channel in texel.iter_mut().take(self.channels) {
+            *channel = 0.0;
+        }
+
+        for (weight, (x, y)) in weights.iter().zip(corners.iter()) {
+            let offset = (y * level.width as usize + x) * self.channels;
+            for (c, channel) in texel.iter_mut().take(self.channels).enumerate() {
+                *channel += weight * level.data[offset + c];
+            }
+        }
+
+        texel
+    }
+}
+
+impl Level {
+    /// Generates the next mip level with a 2x2 box filter, like glGenerateMipmap
+    fn downsample(&self, channels: usize) -> Level {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut data = Vec::with_capacity((width * height) as usize * channels);
+
+        for y in 0..height {
+            for x in 0..width {
+                let xs = [(2 * x).min(self.width - 1), (2 * x + 1).min(self.width - 1)];
+                let ys = [
+                    (2 * y).min(self.height - 1),
+                    (2 * y + 1).min(self.height - 1),
+                ];
+
+                for c in 0..channels {
+                    let mut sum = 0.0;
+                    for sy in ys.iter() {
+                        for sx in xs.iter() {
+                            sum += self.data[((sy * self.width + sx) as usize) * channels + c];
+                        }
+                    }
+                    data.push(sum / 4.0);
+                }
+            }
+        }
+
+        Level {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+impl Wrap {
+    fn index(self, i: i64, len: u32) -> usize {
+        let len = len as i64;
+        let i = match self {
+            Wrap::ClampToEdge => i.max(0).min(len - 1),
+            Wrap::MirroredRepeat => {
+                let period = i.rem_euclid(2 * len);
+                if period < len {
+                    period
+                } else {
+                    2 * len - 1 - period
+                }
+            }
+        };
+
+        i as usize
+    }
+}
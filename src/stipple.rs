@@ -1,18 +1,30 @@
+use serde::{Deserialize, Serialize};
+
 /// Represents a stippled brush stroke, with the parameters
+/// - transform: [[f32; 3]; 2]: an affine transform of the stipple quad, applied before scale, rotation and translation
 /// - translation: [f32; 2] from -1 to 1
 /// - scale: [f32; 2] from -`inf` to +`inf`: controls the size of the stipple bounds
 /// - colormap_scale: [f32; 2] from -`inf` to +`inf`: controls the colormap rate of change.  large values produce more color variance
+/// - colormap_translation: [f32; 2] from -2 to 2: offsets the colormap lookup, so the stipple picks up color from elsewhere
+/// - colormap_rotation: f32 from -`inf` to +`inf` (radians): rotates the colormap lookup around the stipple center
 /// - rotation: [f32; 2] from -`inf` to +`inf` (radians)
 /// - texture_rotation: f32 from -`inf` to +`inf` (radians): rotates the (optionally) bound texture, relative to the stipple location
 /// - gamma: f32 from 0 to +`inf`: exponential gamma exposure, applied to the mask/alpha channel
-#[derive(Clone, Debug)]
+/// - tint: [f32; 4] from 0 to 1: mixes the colormap color toward tint.rgb, by tint.a
+/// - opacity: f32 from 0 to 1: multiplies the alpha channel, after gamma is applied
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Stipple {
+    pub(crate) transform: [[f32; 3]; 2],
     pub(crate) translation: [f32; 2],
     pub(crate) scale: [f32; 2],
     pub(crate) colormap_scale: [f32; 2],
+    pub(crate) colormap_translation: [f32; 2],
+    pub(crate) colormap_rotation: f32,
     pub(crate) rotation: f32,
     pub(crate) texture_rotation: f32,
     pub(crate) gamma: f32,
+    pub(crate) tint: [f32; 4],
+    pub(crate) opacity: f32,
 }
 
 impl Stipple {
@@ -20,6 +32,17 @@ impl Stipple {
         Self::default()
     }
 
+    /// Applies an affine transform to the stipple quad, given as the rows of a 2x3 matrix.
+    /// The transform is applied in stipple space, before scale, rotation and translation,
+    /// so it can shear or skew strokes (e.g. `[[1.0, 0.5, 0.0], [0.0, 1.0, 0.0]]` shears along x).
+    /// The mask and texture follow the transformed quad, and the colormap is sampled under it.
+    /// Range: [[f32; 3]; 2] from -`inf` to +`inf`
+    /// Default: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] (identity)
+    pub fn with_transform(mut self, transform: [[f32; 3]; 2]) -> Self {
+        self.transform = transform;
+        self
+    }
+
     /// Translates the stipple, relative to the center of the canvas
     /// Range: [f32; 2] from -1 to 1
     pub fn with_translation(mut self, translation: [f32; 2]) -> Self {
@@ -42,6 +65,23 @@ impl Stipple {
         self
     }
 
+    /// Offsets the colormap lookup, relative to the stipple translation.
+    /// The stipple carries color picked up from the offset location, which can simulate smudging and color bleeding.
+    /// Range: [f32; 2] from -2 to 2
+    /// Default: [0.0, 0.0]
+    pub fn with_colormap_translation(mut self, translation: [f32; 2]) -> Self {
+        self.colormap_translation = translation;
+        self
+    }
+
+    /// Rotates the colormap lookup around the stipple center, in addition to the stipple rotation
+    /// Range: f32 from -`inf` to +`inf` (radians)
+    /// Default: 0.0
+    pub fn with_colormap_rotation(mut self, rotation: f32) -> Self {
+        self.colormap_rotation = rotation;
+        self
+    }
+
     /// Rotates the stipple by the given radians
     /// Range: [f32; 2] from -`inf` to +`inf` (radians)
     pub fn with_rotation(mut self, rotation: f32) -> Self {
@@ -63,17 +103,38 @@ impl Stipple {
         self.gamma = gamma;
         self
     }
+
+    /// Mixes the colormap color toward tint.rgb, by tint.a.  This can be used for glazing passes.
+    /// Range: [f32; 4] from 0 to 1
+    /// Default: [1.0, 1.0, 1.0, 0.0] (no tint)
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    /// Multiplies the alpha channel, without changing the mask falloff (unlike gamma)
+    /// Range: f32 from 0 to 1
+    /// Default: 1.0
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
 }
 
 impl Default for Stipple {
     fn default() -> Stipple {
         Stipple {
+            transform: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
             translation: [0.0, 0.0],
             scale: [1.0, 1.0],
             colormap_scale: [1.0, 1.0],
+            colormap_translation: [0.0, 0.0],
+            colormap_rotation: 0.0,
             rotation: 0.0,
             texture_rotation: 0.0,
             gamma: 1.0,
+            tint: [1.0, 1.0, 1.0, 0.0],
+            opacity: 1.0,
         }
     }
 }
@@ -0,0 +1,20 @@
+//! Setup shared by the unit tests
+
+use crate::backend::DaliBackend;
+use crate::{ColormapHandle, DaliPipeline, MaskHandle};
+
+/// Creates a pipeline with a 4x4 colormap, and an opaque 8x8 mask with one mipmap
+pub(crate) fn pipeline<B, F>(
+    backend: B,
+    color: F,
+) -> (DaliPipeline<B>, ColormapHandle<B>, MaskHandle<B>)
+where
+    B: DaliBackend,
+    F: Fn(f32, f32) -> [f32; 4],
+{
+    let mut pipeline = DaliPipeline::new(backend);
+    let colormap = pipeline.colormap([4, 4], color);
+    let mask = pipeline.mask_from_image(image::GrayImage::from_pixel(8, 8, image::Luma([255])), 1);
+
+    (pipeline, colormap, mask)
+}
@@ -1,16 +1,29 @@
-use luminance::pixel::R32F;
-use luminance::shader::program::Program;
+use std::path::{Path, PathBuf};
+
+use luminance::context::GraphicsContext;
+use luminance::shader::program::{Program, ProgramError};
 use luminance::tess::{Mode, Tess, TessBuilder, TessError};
-use luminance::texture::{Dim2, Flat, Texture};
-use luminance_glfw::Surface;
 
 use semantics::*;
 
+use crate::backend::{DaliBackend, GlBackend};
+use crate::mask::MaskFit;
 use crate::texture::semantics::{TextureRendererInterface, Vertex};
+use crate::WindowSurface;
 
 /// A handle to a Dali Texture loaded into GPU memory
-pub struct TextureHandle {
-    pub texture: Texture<Flat, Dim2, R32F>,
+pub struct TextureHandle<B: DaliBackend = GlBackend<WindowSurface>> {
+    pub texture: B::Texture,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) mipmaps: usize,
+    pub(crate) fit: MaskFit,
+}
+
+impl<B: DaliBackend> TextureHandle<B> {
+    /// The image file the texture was loaded from, which is recorded in scene files
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
 }
 
 /// Implements the functionality requires to fully render a mipmapped texture, that can be used as a stipple pattern
@@ -19,11 +32,17 @@ pub struct TextureHandle {
 pub trait TextureRenderer {
     fn compile(&self) -> Program<(), (), TextureRendererInterface>;
 
+    /// Compiles the program, and returns an error if the shader fails to compile.
+    /// The pipeline calls this method, and the default implementation calls compile.
+    fn try_compile(&self) -> Result<Program<(), (), TextureRendererInterface>, ProgramError> {
+        Ok(self.compile())
+    }
+
     fn texture_size(&self) -> [u32; 2];
 
     fn mipmaps(&self) -> usize;
 
-    fn tesselate<S: Surface>(&self, surface: &mut S) -> Result<Tess, TessError> {
+    fn tesselate<C: GraphicsContext>(&self, context: &mut C) -> Result<Tess, TessError> {
         const QUAD: [Vertex; 6] = [
             Vertex {
                 position: VertexPosition::new([-1.0, -1.0]),
@@ -45,7 +64,7 @@ pub trait TextureRenderer {
             },
         ];
 
-        TessBuilder::new(surface)
+        TessBuilder::new(context)
             .add_vertices(QUAD)
             .set_mode(Mode::Triangle)
             .build()
@@ -53,7 +72,7 @@ pub trait TextureRenderer {
 }
 
 pub mod renderers {
-    use luminance::shader::program::Program;
+    use luminance::shader::program::{Program, ProgramError};
 
     use crate::texture::semantics::TextureRendererInterface;
     use crate::texture::TextureRenderer;
@@ -75,23 +94,27 @@ pub mod renderers {
         }
     }
 
-    const GEN_VS: &'static str = include_str!("shaders/gen-vs.glsl");
+    const GEN_VS: &str = include_str!("shaders/gen-vs.glsl");
 
     impl TextureRenderer for FragmentShaderRenderer {
         fn compile(&self) -> Program<(), (), TextureRendererInterface> {
+            self.try_compile()
+                .expect("Should have compiled texture renderer")
+        }
+
+        fn try_compile(&self) -> Result<Program<(), (), TextureRendererInterface>, ProgramError> {
             let gen_program = Program::<(), (), TextureRendererInterface>::from_strings(
                 None,
                 GEN_VS,
                 None,
                 self.fragment_shader.as_str(),
-            )
-            .expect("merge program creation");
+            )?;
 
             for warning in &gen_program.warnings {
                 eprintln!("Warning: {}", warning);
             }
 
-            gen_program.ignore_warnings()
+            Ok(gen_program.ignore_warnings())
         }
 
         fn texture_size(&self) -> [u32; 2] {
@@ -0,0 +1,127 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::os::raw::c_void;
+use std::rc::Rc;
+use std::sync::mpsc::Receiver;
+
+use glfw::{Context, OpenGlProfileHint, SwapInterval, Window, WindowHint, WindowMode};
+use luminance::context::GraphicsContext;
+use luminance::framebuffer::Framebuffer;
+use luminance::state::GraphicsState;
+use luminance::texture::{Dim2, Flat};
+use luminance_glfw::{GlfwSurfaceError, Surface, WindowEvent};
+
+use crate::context::{ContextOptions, GlProfile};
+use crate::error::DaliError;
+
+/// A GLFW window, created with the title, multisampling, visibility, vsync and GL version
+/// configured in [DaliContextBuilder](crate::DaliContextBuilder).
+pub struct WindowSurface {
+    window: Window,
+    events_rx: Receiver<(f64, WindowEvent)>,
+    gfx_state: Rc<RefCell<GraphicsState>>,
+}
+
+unsafe impl GraphicsContext for WindowSurface {
+    fn state(&self) -> &Rc<RefCell<GraphicsState>> {
+        &self.gfx_state
+    }
+}
+
+impl WindowSurface {
+    pub(crate) fn with_options(
+        (width, height): (u32, u32),
+        options: &ContextOptions,
+    ) -> Result<WindowSurface, GlfwSurfaceError> {
+        let mut glfw = glfw::init(glfw::LOG_ERRORS).map_err(GlfwSurfaceError::InitError)?;
+
+        let (major, minor) = options.gl_version;
+        glfw.window_hint(WindowHint::ContextVersion(major, minor));
+        match options.gl_profile {
+            GlProfile::Core => {
+                glfw.window_hint(WindowHint::OpenGlProfile(OpenGlProfileHint::Core));
+                glfw.window_hint(WindowHint::OpenGlForwardCompat(true));
+            }
+            GlProfile::Compatibility => {
+                glfw.window_hint(WindowHint::OpenGlProfile(OpenGlProfileHint::Compat));
+                glfw.window_hint(WindowHint::OpenGlForwardCompat(false));
+            }
+        }
+        glfw.window_hint(WindowHint::Samples(options.num_samples()));
+        glfw.window_hint(WindowHint::Visible(options.visible));
+
+        let (mut window, events_rx) = glfw
+            .create_window(width, height, &options.title, WindowMode::Windowed)
+            .ok_or(GlfwSurfaceError::WindowCreationFailed)?;
+
+        window.make_current();
+        window.set_all_polling(true);
+
+        let swap_interval = if options.vsync {
+            SwapInterval::Sync(1)
+        } else {
+            SwapInterval::None
+        };
+        glfw.set_swap_interval(swap_interval);
+
+        gl::load_with(|s| window.get_proc_address(s) as *const c_void);
+
+        let gfx_state = GraphicsState::new().map_err(GlfwSurfaceError::GraphicsStateError)?;
+
+        Ok(WindowSurface {
+            window,
+            events_rx,
+            gfx_state: Rc::new(RefCell::new(gfx_state)),
+        })
+    }
+}
+
+/// A window which the pipeline can preview into.
+///
+/// Implemented for every luminance [Surface], such as `GlfwSurface`, and for [WindowSurface].
+pub trait PreviewSurface: GraphicsContext {
+    /// The framebuffer of the window
+    fn preview_buffer(&mut self) -> Result<Framebuffer<Flat, Dim2, (), ()>, DaliError>;
+
+    /// Polls the window, and returns the events which have arrived
+    fn preview_events<'a>(&'a mut self) -> Box<dyn Iterator<Item = WindowEvent> + 'a>;
+
+    /// Shows the rendered frame
+    fn present(&mut self);
+}
+
+impl<S> PreviewSurface for S
+where
+    S: Surface<Event = WindowEvent>,
+    S::Error: Debug,
+{
+    fn preview_buffer(&mut self) -> Result<Framebuffer<Flat, Dim2, (), ()>, DaliError> {
+        self.back_buffer()
+            .map_err(|e| DaliError::Surface(format!("{:?}", e)))
+    }
+
+    fn preview_events<'a>(&'a mut self) -> Box<dyn Iterator<Item = WindowEvent> + 'a> {
+        self.poll_events()
+    }
+
+    fn present(&mut self) {
+        self.swap_buffers();
+    }
+}
+
+impl PreviewSurface for WindowSurface {
+    fn preview_buffer(&mut self) -> Result<Framebuffer<Flat, Dim2, (), ()>, DaliError> {
+        let (width, height) = self.window.get_framebuffer_size();
+        let size = [width as u32, height as u32];
+        Ok(Framebuffer::back_buffer(self, size))
+    }
+
+    fn preview_events<'a>(&'a mut self) -> Box<dyn Iterator<Item = WindowEvent> + 'a> {
+        self.window.glfw.poll_events();
+        Box::new(self.events_rx.try_iter().map(|(_, e)| e))
+    }
+
+    fn present(&mut self) {
+        self.window.swap_buffers();
+    }
+}